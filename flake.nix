@@ -51,7 +51,7 @@
                 name = "source";
                 filter =
                   let
-                    isExtraSource = path: _: builtins.match ".+\.(graphql|json)" path != null;
+                    isExtraSource = path: _: builtins.match ".+\.(graphql|json|ttf)" path != null;
                   in
                   path: type: (isExtraSource path type) || (craneLib.filterCargoSources path type);
               };
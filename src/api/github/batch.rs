@@ -0,0 +1,202 @@
+//! Builder of queries that fetch multiple repositories in a single request.
+//!
+//! A static query can only fetch a fixed number of repositories, so the query
+//! is built at runtime by aliasing the `repository` field for each repository:
+//!
+//! ```graphql
+//! query BatchQuery($owner0: String!, $name0: String!, ...) {
+//!   rateLimit { cost remaining resetAt }
+//!   r0: repository(owner: $owner0, name: $name0) { ... }
+//!   r1: repository(owner: $owner1, name: $name1) { ... }
+//! }
+//! ```
+//!
+//! The selection sets mirror the static queries in this directory, so each
+//! aliased field can be deserialized into the types generated from them.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::rate_limit::{RateLimit, RateLimited};
+
+/// Selection set equivalent to `repository` in `stargazers.graphql`, without
+/// the `before` cursor.
+pub fn stargazers_selection(count: i64) -> String {
+    format!(
+        "{{
+    stargazers(last: {count}) {{
+      totalCount
+      pageInfo {{
+        startCursor
+        hasPreviousPage
+      }}
+      edges {{
+        starredAt
+        node {{
+          login
+        }}
+      }}
+    }}
+  }}"
+    )
+}
+
+/// Selection set equivalent to `repository` in `commit-history.graphql`,
+/// without the `after` cursor.
+pub fn commit_history_selection(count: i64) -> String {
+    format!(
+        "{{
+    defaultBranchRef {{
+      target {{
+        __typename
+        ... on Commit {{
+          oid
+          history(first: {count}) {{
+            totalCount
+            nodes {{
+              author {{
+                email
+                user {{
+                  login
+                }}
+              }}
+              committedDate
+              message
+            }}
+          }}
+        }}
+      }}
+    }}
+  }}"
+    )
+}
+
+#[derive(Debug, Default)]
+pub struct BatchQuery {
+    declarations: Vec<String>,
+    fields: Vec<String>,
+    variables: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchQueryBody {
+    query: String,
+    variables: serde_json::Map<String, serde_json::Value>,
+    #[serde(rename = "operationName")]
+    operation_name: &'static str,
+}
+
+impl BatchQuery {
+    /// Add a repository with the given selection set, and return the alias of
+    /// the field.
+    pub fn add_repository(&mut self, owner: &str, name: &str, selection: &str) -> String {
+        let i = self.fields.len();
+        let alias = format!("r{}", i);
+
+        self.declarations
+            .push(format!("$owner{i}: String!, $name{i}: String!"));
+        self.variables
+            .insert(format!("owner{i}"), serde_json::Value::from(owner));
+        self.variables
+            .insert(format!("name{i}"), serde_json::Value::from(name));
+        self.fields.push(format!(
+            "{alias}: repository(owner: $owner{i}, name: $name{i}) {selection}"
+        ));
+
+        alias
+    }
+
+    pub fn build(self) -> BatchQueryBody {
+        let query = format!(
+            "query BatchQuery({}) {{
+  rateLimit {{
+    cost
+    remaining
+    resetAt
+  }}
+  {}
+}}",
+            self.declarations.join(", "),
+            self.fields.join("\n  ")
+        );
+
+        BatchQueryBody {
+            query,
+            variables: self.variables,
+            operation_name: "BatchQuery",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRateLimit {
+    cost: i64,
+    remaining: i64,
+    #[serde(rename = "resetAt")]
+    reset_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchResponseData {
+    #[serde(rename = "rateLimit")]
+    rate_limit: Option<BatchRateLimit>,
+    /// Repositories keyed by their aliases.
+    #[serde(flatten)]
+    pub repositories: HashMap<String, Option<serde_json::Value>>,
+}
+
+impl RateLimited for BatchResponseData {
+    fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+            .as_ref()
+            .and_then(|r| RateLimit::new(r.cost, r.remaining, &r.reset_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_batch_query() {
+        let mut batch = BatchQuery::default();
+        let selection = "{ name }";
+        assert_eq!(batch.add_repository("akirak", "yastar", selection), "r0");
+        assert_eq!(batch.add_repository("akirak", "twind.el", selection), "r1");
+
+        let body = serde_json::to_value(batch.build()).unwrap();
+
+        assert_eq!(
+            body["query"],
+            "query BatchQuery($owner0: String!, $name0: String!, $owner1: String!, $name1: String!) {
+  rateLimit {
+    cost
+    remaining
+    resetAt
+  }
+  r0: repository(owner: $owner0, name: $name0) { name }
+  r1: repository(owner: $owner1, name: $name1) { name }
+}"
+        );
+        assert_eq!(body["variables"]["name1"], "twind.el");
+        assert_eq!(body["operationName"], "BatchQuery");
+    }
+
+    #[test]
+    fn test_parse_batch_response() {
+        let data: BatchResponseData = serde_json::from_str(
+            r#"{
+              "rateLimit": {"cost": 1, "remaining": 4999, "resetAt": "2024-11-24T10:00:00Z"},
+              "r0": {"name": "yastar"},
+              "r1": null
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(data.rate_limit().unwrap().remaining, 4999);
+        assert!(data.repositories["r0"].is_some());
+        assert!(data.repositories["r1"].is_none());
+        assert_eq!(data.repositories.len(), 2);
+    }
+}
@@ -0,0 +1,219 @@
+//! Errors returned by the GitHub client.
+
+use std::fmt;
+use std::time::Duration;
+
+use graphql_client::PathFragment;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+/// Error types of GraphQL errors that are likely to succeed on retry.
+const TRANSIENT_ERROR_TYPES: &[&str] =
+    &["INTERNAL", "SERVICE_UNAVAILABLE", "TIMEOUT", "RATE_LIMITED"];
+
+/// GitHub returns this message without an error type when a query times out.
+const TIMEOUT_MESSAGE_PREFIX: &str = "Something went wrong while executing your query";
+
+/// An entry of the `errors` array of a GraphQL response.
+///
+/// Unlike `graphql_client::Error`, this retains the `type` field GitHub sets on
+/// each error.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GraphQLError {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub error_type: Option<String>,
+    /// Path to the field the error applies to, e.g. `["repository"]`.
+    pub path: Option<Vec<PathFragment>>,
+}
+
+impl GraphQLError {
+    pub fn is_transient(&self) -> bool {
+        match self.error_type {
+            Some(ref error_type) => TRANSIENT_ERROR_TYPES.contains(&error_type.as_str()),
+            None => self.message.starts_with(TIMEOUT_MESSAGE_PREFIX),
+        }
+    }
+
+    fn has_type(&self, error_type: &str) -> bool {
+        self.error_type.as_deref() == Some(error_type)
+    }
+}
+
+impl fmt::Display for GraphQLError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref error_type) = self.error_type {
+            write!(f, "[{}] ", error_type)?;
+        }
+        if let Some(ref path) = self.path {
+            let path = path.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            write!(f, "{}: ", path.join("."))?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum GitHubError {
+    /// The request could not be completed, e.g. on a timeout or a connection
+    /// reset.
+    Http(reqwest::Error),
+    /// The API token was rejected.
+    Unauthorized(String),
+    /// The server responded with an unsuccessful HTTP status. It contains the
+    /// message of the response body, if any.
+    Status(StatusCode, Option<String>),
+    /// The request was rejected by a rate limit and should be resent after the
+    /// delay.
+    RateLimited(Duration),
+    /// The requested resource does not exist or is not visible to the token.
+    NotFound(GraphQLError),
+    /// The token is not allowed to access the resource.
+    Forbidden(GraphQLError),
+    /// The response contains errors not covered by the other variants.
+    GraphQL(Vec<GraphQLError>),
+    /// The response lacks a field the client relies on.
+    UnexpectedResponse(&'static str),
+}
+
+impl GitHubError {
+    /// Build an error from the `errors` array of a response, if any.
+    pub fn from_graphql_errors(errors: &[GraphQLError]) -> Option<Self> {
+        if errors.is_empty() {
+            None
+        } else if let Some(error) = errors.iter().find(|e| e.has_type("NOT_FOUND")) {
+            Some(Self::NotFound(error.clone()))
+        } else if let Some(error) = errors.iter().find(|e| e.has_type("FORBIDDEN")) {
+            Some(Self::Forbidden(error.clone()))
+        } else {
+            Some(Self::GraphQL(errors.to_vec()))
+        }
+    }
+
+    /// Whether the request is worth retrying.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Http(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            Self::Status(status, _) => status.is_server_error(),
+            Self::RateLimited(_) => true,
+            Self::GraphQL(errors) => errors.iter().any(GraphQLError::is_transient),
+            _ => false,
+        }
+    }
+
+    /// Whether the error is specific to the requested resource, so the other
+    /// resources can still be fetched.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Self::NotFound(_) | Self::Forbidden(_))
+    }
+}
+
+impl fmt::Display for GitHubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "HTTP request failed: {}", e),
+            Self::Unauthorized(message) => write!(
+                f,
+                "the API token was rejected ({}), check GITHUB_API_TOKEN",
+                message
+            ),
+            Self::Status(status, Some(message)) => {
+                write!(f, "server responded with {}: {}", status, message)
+            }
+            Self::Status(status, None) => write!(f, "server responded with {}", status),
+            Self::RateLimited(delay) => {
+                write!(f, "rate limit exceeded, retry after {}s", delay.as_secs())
+            }
+            Self::NotFound(error) => write!(f, "not found: {}", error),
+            Self::Forbidden(error) => write!(f, "forbidden: {}", error),
+            Self::GraphQL(errors) => {
+                write!(f, "GraphQL errors:")?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+            Self::UnexpectedResponse(message) => write!(f, "unexpected response: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GitHubError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Body of an unsuccessful REST-style response, e.g. on a bad token.
+#[derive(Debug, Deserialize)]
+pub struct ErrorBody {
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graphql_error(error_type: Option<&str>, message: &str) -> GraphQLError {
+        GraphQLError {
+            message: message.to_string(),
+            error_type: error_type.map(str::to_string),
+            path: None,
+        }
+    }
+
+    #[test]
+    fn test_classify_graphql_errors() {
+        let not_found = GitHubError::from_graphql_errors(&[graphql_error(
+            Some("NOT_FOUND"),
+            "Could not resolve to a Repository with the name 'akirak/missing'.",
+        )])
+        .unwrap();
+        assert!(matches!(not_found, GitHubError::NotFound(_)));
+        assert!(not_found.is_permanent());
+        assert!(!not_found.is_transient());
+
+        let timeout = GitHubError::from_graphql_errors(&[graphql_error(
+            None,
+            "Something went wrong while executing your query. This may be the result of a timeout.",
+        )])
+        .unwrap();
+        assert!(timeout.is_transient());
+        assert!(!timeout.is_permanent());
+
+        assert!(GitHubError::from_graphql_errors(&[]).is_none());
+    }
+
+    #[test]
+    fn test_display_graphql_errors() {
+        let errors: Vec<GraphQLError> = serde_json::from_str(
+            r#"[
+              {
+                "type": "NOT_FOUND",
+                "path": ["repository"],
+                "locations": [{"line": 7, "column": 3}],
+                "message": "Could not resolve to a Repository with the name 'akirak/missing'."
+              },
+              {
+                "path": ["repository", "stargazers", "edges", 0],
+                "message": "Something else"
+              }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            GitHubError::from_graphql_errors(&errors).unwrap().to_string(),
+            "not found: [NOT_FOUND] repository: Could not resolve to a Repository with the name 'akirak/missing'."
+        );
+        assert_eq!(
+            GitHubError::GraphQL(errors).to_string(),
+            "GraphQL errors:
+  - [NOT_FOUND] repository: Could not resolve to a Repository with the name 'akirak/missing'.
+  - repository.stargazers.edges.0: Something else"
+        );
+    }
+}
@@ -1,14 +1,89 @@
+use std::time::Duration;
+
 use anyhow::anyhow;
-use graphql_client::{GraphQLQuery, Response};
-use reqwest::IntoUrl;
+use chrono::Utc;
+use graphql_client::{GraphQLQuery, PathFragment};
+use reqwest::{header::HeaderMap, IntoUrl, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::warn;
+
+mod batch;
+mod error;
+mod rate_limit;
+mod retry;
+
+use batch::{BatchQuery, BatchResponseData};
+pub use error::GitHubError;
+use error::{ErrorBody, GraphQLError};
+use rate_limit::{RateLimit, RateLimited, RateLimiter};
+pub use retry::RetryPolicy;
 
 pub const GRAPHQL_ENDPOINT: &str = "https://api.github.com/graphql";
 
+/// Maximum number of times a request is resent after being rejected by a
+/// secondary rate limit.
+const MAX_RATE_LIMIT_RETRIES: usize = 5;
+
+/// Wait time applied to a secondary rate limit without a `Retry-After` header,
+/// as recommended in the GitHub documentation.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Maximum number of items in a page of a connection allowed by the API.
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Maximum number of repositories fetched in a single batch query.
+pub const BATCH_SIZE: usize = 10;
+
 pub struct GitHubClient<U> {
     client: reqwest::Client,
     endpoint: U,
+    rate_limiter: RateLimiter,
+    retry_policy: RetryPolicy,
+}
+
+/// Response of a GraphQL query.
+#[derive(Debug, Deserialize)]
+pub struct GraphQLResponse<Data> {
+    pub data: Option<Data>,
+    pub errors: Option<Vec<GraphQLError>>,
+}
+
+impl<Data> GraphQLResponse<Data> {
+    /// Return the data of the response. Errors accompanying the data, i.e. a
+    /// partial response, are logged as warnings unless they are permanent.
+    pub fn into_data(self) -> Result<Data, GitHubError> {
+        let errors = self.errors.unwrap_or_default();
+        match (self.data, GitHubError::from_graphql_errors(&errors)) {
+            (Some(_), Some(error)) if error.is_permanent() => Err(error),
+            (Some(data), _) => {
+                for error in errors.iter() {
+                    warn!(error = %error, "partial response");
+                }
+                Ok(data)
+            }
+            (None, Some(error)) => Err(error),
+            (None, None) => Err(GitHubError::UnexpectedResponse("response has no data")),
+        }
+    }
+}
+
+macro_rules! impl_rate_limited {
+    ($module:ident) => {
+        impl RateLimited for $module::ResponseData {
+            fn rate_limit(&self) -> Option<RateLimit> {
+                self.rate_limit
+                    .as_ref()
+                    .and_then(|r| RateLimit::new(r.cost, r.remaining, &r.reset_at))
+            }
+        }
+    };
 }
 
+// Required by the rateLimit field of every query
+type DateTime = String;
+// Required by the oid field of CommitHistoryQuery
+type GitObjectID = String;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "src/api/github/schema.docs.graphql",
@@ -17,6 +92,8 @@ pub struct GitHubClient<U> {
 )]
 struct LoginQuery;
 
+impl_rate_limited!(login_query);
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "src/api/github/schema.docs.graphql",
@@ -25,10 +102,9 @@ struct LoginQuery;
 )]
 struct StarredOwnReposQuery;
 
-pub type ResponseRepoEntry = starred_own_repos_query::StarredOwnReposQueryViewerRepositoriesNodes;
+impl_rate_limited!(starred_own_repos_query);
 
-// Required in CommitHistoryQuery
-type DateTime = String;
+pub type ResponseRepoEntry = starred_own_repos_query::StarredOwnReposQueryViewerRepositoriesNodes;
 
 #[derive(GraphQLQuery)]
 #[graphql(
@@ -38,6 +114,8 @@ type DateTime = String;
 )]
 struct CommitHistoryQuery;
 
+impl_rate_limited!(commit_history_query);
+
 pub type CommitEntry =
     commit_history_query::CommitHistoryQueryRepositoryDefaultBranchRefTargetOnCommitHistoryNodes;
 
@@ -49,14 +127,122 @@ pub type CommitEntry =
 )]
 struct StargazersQuery;
 
+impl_rate_limited!(stargazers_query);
+
 pub type StargazerEntry = stargazers_query::StargazersQueryRepositoryStargazersEdges;
 
+type CommitHead = commit_history_query::CommitHistoryQueryRepositoryDefaultBranchRefTargetOnCommit;
+
+type CommitHistory =
+    commit_history_query::CommitHistoryQueryRepositoryDefaultBranchRefTargetOnCommitHistory;
+
+/// A page returned by [`GitHubClient::get_stargazers_page`].
+pub struct StargazersPage {
+    pub total_count: i64,
+    pub items: Vec<StargazerEntry>,
+    /// Cursor for fetching the remaining stargazers, if any.
+    pub previous_cursor: Option<String>,
+}
+
+impl StargazersPage {
+    fn new(
+        stargazers: stargazers_query::StargazersQueryRepositoryStargazers,
+        accum_count: i64,
+        total_count: i64,
+        first_page: bool,
+    ) -> Result<Self, GitHubError> {
+        let total_count = if first_page {
+            stargazers.total_count
+        } else {
+            total_count
+        };
+
+        let edges = stargazers
+            .edges
+            .ok_or(GitHubError::UnexpectedResponse("missing edges field"))?;
+
+        let mut items = Vec::new();
+        let mut accum_count = accum_count;
+
+        for item in edges.into_iter().flatten() {
+            items.push(item);
+            accum_count += 1;
+            if accum_count == total_count {
+                break;
+            }
+        }
+
+        let page_info = stargazers.page_info;
+        let previous_cursor = if page_info.has_previous_page && accum_count < total_count {
+            page_info.start_cursor
+        } else {
+            None
+        };
+
+        Ok(Self {
+            total_count,
+            items,
+            previous_cursor,
+        })
+    }
+}
+
+/// Request of the first page of stargazers in a batch.
+pub struct StargazersPageRequest {
+    pub owner: String,
+    pub name: String,
+    pub accum_count: i64,
+    pub total_count: i64,
+}
+
+fn stargazers_page_count(accum_count: i64, total_count: i64, page_size: i64) -> i64 {
+    std::cmp::min(total_count - accum_count + 5, page_size)
+}
+
+fn commit_head_of(
+    repository: commit_history_query::CommitHistoryQueryRepository,
+) -> Result<CommitHead, GitHubError> {
+    let target = repository
+        .default_branch_ref
+        .ok_or(GitHubError::UnexpectedResponse(
+            "missing default_branch_ref field",
+        ))?
+        .target
+        .ok_or(GitHubError::UnexpectedResponse("missing target field"))?;
+
+    match target {
+        commit_history_query::CommitHistoryQueryRepositoryDefaultBranchRefTarget::Commit(head) => {
+            Ok(head)
+        }
+        _ => Err(GitHubError::UnexpectedResponse("non-commit head")),
+    }
+}
+
+/// Return a cursor pointing to the last `count` commits of the history of the
+/// head, or `None` if the history fits in a page of that size.
+///
+/// A history cursor consists of the object ID of the head and the offset of a
+/// commit, and it can be built without paging through the history.
+fn tail_cursor(head_oid: &str, total_count: i64, count: i64) -> Option<String> {
+    (total_count > count).then(|| format!("{} {}", head_oid, total_count - count - 1))
+}
+
+fn into_commits(history: CommitHistory) -> Option<Vec<CommitEntry>> {
+    let commits = history
+        .nodes
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    (!commits.is_empty()).then_some(commits)
+}
+
 impl<U: IntoUrl + Copy + reqwest::IntoUrl> GitHubClient<U> {
     // Most of this code has been just stolen from
     // https://github.com/graphql-rust/graphql-client/blob/main/examples/github/examples/github.rs
     pub fn new(endpoint: U) -> anyhow::Result<Self> {
-        let github_api_token =
-            std::env::var("GITHUB_API_TOKEN").expect("Missing GITHUB_API_TOKEN env var");
+        let github_api_token = std::env::var("GITHUB_API_TOKEN")
+            .map_err(|_| anyhow!("Missing GITHUB_API_TOKEN env var"))?;
 
         let client = reqwest::Client::builder()
             .user_agent("github-statistics")
@@ -64,44 +250,248 @@ impl<U: IntoUrl + Copy + reqwest::IntoUrl> GitHubClient<U> {
                 std::iter::once((
                     reqwest::header::AUTHORIZATION,
                     reqwest::header::HeaderValue::from_str(&format!("Bearer {}", github_api_token))
-                        .unwrap(),
+                        .map_err(|_| anyhow!("GITHUB_API_TOKEN contains invalid characters"))?,
                 ))
                 .collect(),
             )
             .build()?;
 
-        Ok(Self { client, endpoint })
+        Ok(Self {
+            client,
+            endpoint,
+            rate_limiter: RateLimiter::default(),
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set the name under which the API cost of subsequent queries is
+    /// accounted.
+    pub fn set_phase(&self, phase: &'static str) {
+        self.rate_limiter.set_phase(phase);
+    }
+
+    /// Return rate limit points spent in each phase.
+    pub fn points_by_phase(&self) -> Vec<(&'static str, i64)> {
+        self.rate_limiter.points_by_phase()
+    }
+
+    pub fn remaining_points(&self) -> Option<i64> {
+        self.rate_limiter.remaining()
+    }
+
+    async fn post_query<Q: GraphQLQuery>(
+        &self,
+        variables: Q::Variables,
+    ) -> Result<GraphQLResponse<Q::ResponseData>, GitHubError>
+    where
+        Q::ResponseData: RateLimited,
+    {
+        self.post(&Q::build_query(variables)).await
+    }
+
+    async fn post<B, Data>(&self, body: &B) -> Result<GraphQLResponse<Data>, GitHubError>
+    where
+        B: Serialize,
+        Data: DeserializeOwned + RateLimited,
+    {
+        let mut attempts = 1;
+        let mut rate_limit_retries = 0;
+
+        loop {
+            self.rate_limiter.wait().await;
+
+            let error = match self.send(body).await {
+                Ok(response) => break Ok(response),
+                Err(e) => e,
+            };
+
+            let delay = match error {
+                GitHubError::RateLimited(delay) if rate_limit_retries < MAX_RATE_LIMIT_RETRIES => {
+                    rate_limit_retries += 1;
+                    delay
+                }
+                _ if error.is_transient() && attempts < self.retry_policy.max_attempts => {
+                    let delay = self.retry_policy.backoff(attempts);
+                    attempts += 1;
+                    delay
+                }
+                _ => break Err(error),
+            };
+
+            warn!(
+                error = %error,
+                seconds = delay.as_secs(),
+                "request failed, waiting before retrying"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Send a query once, and check the response for errors that affect the
+    /// whole request.
+    async fn send<B, Data>(&self, body: &B) -> Result<GraphQLResponse<Data>, GitHubError>
+    where
+        B: Serialize,
+        Data: DeserializeOwned + RateLimited,
+    {
+        let reqwest_response = self
+            .client
+            .post(self.endpoint)
+            .json(body)
+            .send()
+            .await
+            .map_err(GitHubError::Http)?;
+
+        let status = reqwest_response.status();
+
+        if let Some(delay) = self.rate_limit_delay(status, reqwest_response.headers()) {
+            return Err(GitHubError::RateLimited(delay));
+        }
+
+        if !status.is_success() {
+            let message = reqwest_response
+                .json::<ErrorBody>()
+                .await
+                .ok()
+                .map(|body| body.message);
+            return Err(if status == StatusCode::UNAUTHORIZED {
+                GitHubError::Unauthorized(message.unwrap_or_else(|| status.to_string()))
+            } else {
+                GitHubError::Status(status, message)
+            });
+        }
+
+        let response: GraphQLResponse<Data> =
+            reqwest_response.json().await.map_err(GitHubError::Http)?;
+
+        if let Some(rate_limit) = response.data.as_ref().and_then(|data| data.rate_limit()) {
+            self.rate_limiter.record(&rate_limit);
+        }
+
+        if let Some(error) = response
+            .errors
+            .as_deref()
+            .and_then(GitHubError::from_graphql_errors)
+        {
+            // Other errors accompanied by data are left to the caller
+            if response.data.is_none() || error.is_transient() {
+                return Err(error);
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Send a batch query, and return the result for each alias in order.
+    async fn post_batch<T: DeserializeOwned>(
+        &self,
+        batch: BatchQuery,
+        aliases: &[String],
+    ) -> Result<Vec<Result<T, GitHubError>>, GitHubError> {
+        let response = self.post::<_, BatchResponseData>(&batch.build()).await?;
+
+        let errors = response.errors.unwrap_or_default();
+        let mut data = response
+            .data
+            .ok_or(GitHubError::UnexpectedResponse("response has no data"))?;
+
+        let alias_of = |error: &GraphQLError| match error.path.as_deref() {
+            Some([PathFragment::Key(alias), ..]) => Some(alias.clone()),
+            _ => None,
+        };
+
+        for error in errors.iter() {
+            if alias_of(error).is_none_or(|alias| !aliases.contains(&alias)) {
+                warn!(error = %error, "partial response");
+            }
+        }
+
+        let results = aliases
+            .iter()
+            .map(|alias| {
+                let alias_errors = errors
+                    .iter()
+                    .filter(|error| alias_of(error).as_ref() == Some(alias))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let error = GitHubError::from_graphql_errors(&alias_errors);
+
+                match (data.repositories.remove(alias).flatten(), error) {
+                    (_, Some(error)) if error.is_permanent() => Err(error),
+                    (Some(value), _) => serde_json::from_value(value)
+                        .map_err(|_| GitHubError::UnexpectedResponse("malformed repository field")),
+                    (None, Some(error)) => Err(error),
+                    (None, None) => {
+                        Err(GitHubError::UnexpectedResponse("missing repository field"))
+                    }
+                }
+            })
+            .collect();
+
+        Ok(results)
+    }
+
+    /// Return how long to wait before resending a request rejected by a rate
+    /// limit, or `None` if the request was not rejected.
+    fn rate_limit_delay(&self, status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+        if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+            return None;
+        }
+
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<i64>().ok())
+        };
+
+        // Secondary rate limit
+        if let Some(seconds) = header("retry-after") {
+            return Some(Duration::from_secs(seconds.max(0) as u64));
+        }
+
+        // Primary rate limit
+        if header("x-ratelimit-remaining") == Some(0) {
+            if let Some(reset_at) = header("x-ratelimit-reset")
+                .and_then(|timestamp| chrono::DateTime::<Utc>::from_timestamp(timestamp, 0))
+            {
+                self.rate_limiter.exhaust_until(reset_at);
+                return Some(Duration::ZERO);
+            }
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            Some(DEFAULT_RETRY_AFTER)
+        } else {
+            None
+        }
     }
 
     pub async fn get_owned_repositories(
         &self,
         after: Option<String>,
-    ) -> anyhow::Result<Response<starred_own_repos_query::ResponseData>> {
+    ) -> Result<GraphQLResponse<starred_own_repos_query::ResponseData>, GitHubError> {
         let variables = starred_own_repos_query::Variables { after };
 
-        let response = {
-            let client = &self.client;
-            let url = self.endpoint;
-            async move {
-                let body = <StarredOwnReposQuery>::build_query(variables);
-                let reqwest_response = client.post(url).json(&body).send().await?;
-                reqwest_response.json().await
-            }
-        }
-        .await?;
+        let response = self.post_query::<StarredOwnReposQuery>(variables).await?;
 
         Ok(response)
     }
 
     pub async fn get_all_starred_own_repositories(
         &self,
-    ) -> anyhow::Result<(String, Vec<ResponseRepoEntry>)> {
+    ) -> Result<(String, Vec<ResponseRepoEntry>), GitHubError> {
         let mut result = Vec::new();
         let mut cursor: Option<String> = None;
 
         loop {
             let response = self.get_owned_repositories(cursor).await?;
-            let data = response.data.unwrap().viewer;
+            let data = response.into_data()?.viewer;
             match data.repositories.nodes {
                 None => {
                     break Ok((data.login, result));
@@ -130,7 +520,7 @@ impl<U: IntoUrl + Copy + reqwest::IntoUrl> GitHubClient<U> {
         name: String,
         count: i64,
         before: Option<String>,
-    ) -> anyhow::Result<stargazers_query::StargazersQueryRepositoryStargazers> {
+    ) -> Result<stargazers_query::StargazersQueryRepositoryStargazers, GitHubError> {
         let variables = stargazers_query::Variables {
             owner,
             name,
@@ -138,175 +528,206 @@ impl<U: IntoUrl + Copy + reqwest::IntoUrl> GitHubClient<U> {
             before,
         };
 
-        let response: Response<stargazers_query::ResponseData> = {
-            let client = &self.client;
-            let url = self.endpoint;
-            async move {
-                let body = <StargazersQuery>::build_query(variables);
-                let reqwest_response = client.post(url).json(&body).send().await?;
-                reqwest_response.json().await
-            }
-        }
-        .await?;
+        let response = self.post_query::<StargazersQuery>(variables).await?;
 
         let stargazers = response
-            .data
-            .ok_or(anyhow!("response has no data"))?
+            .into_data()?
             .repository
-            .ok_or(anyhow!("missing repository field"))?
+            .ok_or(GitHubError::UnexpectedResponse("missing repository field"))?
             .stargazers;
 
         Ok(stargazers)
     }
 
-    pub async fn get_stargazers_after_count(
+    /// Fetch all stargazers of a repository, e.g. to find out who has removed
+    /// their stars.
+    pub async fn get_all_stargazers(
         &self,
         owner: String,
         name: String,
-        after_count: i64,
-        expected_total_count: i64,
-        page_size: i64,
-    ) -> anyhow::Result<(i64, Vec<StargazerEntry>)> {
-        let mut result = Vec::new();
-        let mut cursor = None;
-        let mut accum_count = after_count;
-        let mut total_count = expected_total_count;
+    ) -> Result<Vec<StargazerEntry>, GitHubError> {
+        let mut before = None;
+        let mut items = Vec::new();
 
         loop {
-            let count = std::cmp::min(total_count - accum_count + 5, page_size);
-            let page = self
-                .get_stargazers(owner.to_owned(), name.to_owned(), count, cursor.to_owned())
+            let stargazers = self
+                .get_stargazers(owner.clone(), name.clone(), MAX_PAGE_SIZE, before)
                 .await?;
 
-            total_count = page.total_count;
-
-            let edges = page.edges.ok_or(anyhow!("missing edges field"))?;
-
-            for edge in edges.iter() {
-                match edge {
-                    Some(item) => {
-                        result.push(item.clone());
-                        accum_count += 1;
-                        if accum_count == total_count {
-                            break;
-                        }
-                    }
-                    None => {}
-                }
+            items.extend(
+                stargazers
+                    .edges
+                    .ok_or(GitHubError::UnexpectedResponse("missing edges field"))?
+                    .into_iter()
+                    .flatten(),
+            );
+
+            let page_info = stargazers.page_info;
+            match page_info.start_cursor {
+                Some(cursor) if page_info.has_previous_page => before = Some(cursor),
+                _ => break,
             }
+        }
 
-            if !page.page_info.has_previous_page || accum_count >= total_count {
-                break;
-            }
+        Ok(items)
+    }
 
-            cursor = page.page_info.start_cursor;
-        }
+    /// Fetch a page of stargazers, going backwards from the most recent one.
+    ///
+    /// `accum_count` is the number of stargazers already known, and
+    /// `total_count` is the expected number of stargazers after the fetch.
+    /// The total count reported by the API is used instead on the first page,
+    /// i.e. when `before` is `None`.
+    pub async fn get_stargazers_page(
+        &self,
+        owner: String,
+        name: String,
+        accum_count: i64,
+        total_count: i64,
+        page_size: i64,
+        before: Option<String>,
+    ) -> Result<StargazersPage, GitHubError> {
+        let count = stargazers_page_count(accum_count, total_count, page_size);
+        let first_page = before.is_none();
+        let stargazers = self.get_stargazers(owner, name, count, before).await?;
 
-        Ok((total_count, result))
+        StargazersPage::new(stargazers, accum_count, total_count, first_page)
+    }
+
+    /// Fetch the first page of stargazers of multiple repositories in a single
+    /// request. See [`Self::get_stargazers_page`] for the arguments.
+    pub async fn get_stargazers_pages_batch(
+        &self,
+        requests: &[StargazersPageRequest],
+        page_size: i64,
+    ) -> Result<Vec<Result<StargazersPage, GitHubError>>, GitHubError> {
+        let mut batch = BatchQuery::default();
+        let aliases = requests
+            .iter()
+            .map(|request| {
+                let count =
+                    stargazers_page_count(request.accum_count, request.total_count, page_size);
+                batch.add_repository(
+                    &request.owner,
+                    &request.name,
+                    &batch::stargazers_selection(count),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let results = self
+            .post_batch::<stargazers_query::StargazersQueryRepository>(batch, &aliases)
+            .await?;
+
+        Ok(results
+            .into_iter()
+            .zip(requests)
+            .map(|(result, request)| {
+                StargazersPage::new(
+                    result?.stargazers,
+                    request.accum_count,
+                    request.total_count,
+                    true,
+                )
+            })
+            .collect())
     }
 
     pub async fn get_commit_history(
         &self,
         owner: String,
         name: String,
+        count: i64,
         after: Option<String>,
-    ) -> anyhow::Result<
-        commit_history_query::CommitHistoryQueryRepositoryDefaultBranchRefTargetOnCommitHistory,
-    > {
-        let variables = commit_history_query::Variables { owner, name, after };
-
-        let response: Response<commit_history_query::ResponseData> = {
-            let client = &self.client;
-            let url = self.endpoint;
-            async move {
-                let body = <CommitHistoryQuery>::build_query(variables);
-                let reqwest_response = client.post(url).json(&body).send().await?;
-                reqwest_response.json().await
-            }
-        }
-        .await?;
+    ) -> Result<CommitHead, GitHubError> {
+        let variables = commit_history_query::Variables {
+            owner,
+            name,
+            count,
+            after,
+        };
 
-        let target = response
-            .data
-            .ok_or(anyhow!("response has no data"))?
+        let response = self.post_query::<CommitHistoryQuery>(variables).await?;
+
+        let repository = response
+            .into_data()?
             .repository
-            .ok_or(anyhow!("missing repository field"))?
-            .default_branch_ref
-            .ok_or(anyhow!("missing default_branch_ref field"))?
-            .target
-            .ok_or(anyhow!("missing target field"))?;
-
-        match target {
-            commit_history_query::CommitHistoryQueryRepositoryDefaultBranchRefTarget::Commit(
-                head,
-            ) => Ok(head.history),
-            _ => Err(anyhow::format_err!("non-commit head")),
-        }
+            .ok_or(GitHubError::UnexpectedResponse("missing repository field"))?;
+
+        commit_head_of(repository)
     }
 
+    /// Fetch the oldest commits on the default branch, up to the count limit.
+    /// It takes at most two requests regardless of the length of the history.
+    #[cfg(test)]
     pub async fn get_first_commits(
         &self,
         owner: String,
         name: String,
         count_limit: usize,
-    ) -> anyhow::Result<Option<Vec<CommitEntry>>> {
-        let mut cursor = None;
-        let mut previous_cursor = None;
+    ) -> Result<Option<Vec<CommitEntry>>, GitHubError> {
+        let count = count_limit as i64;
+        let head = self
+            .get_commit_history(owner.clone(), name.clone(), count, None)
+            .await?;
 
-        let mut count = 0;
-        let mut commits = Vec::new();
-
-        loop {
-            let history = self
-                .get_commit_history(owner.clone(), name.clone(), cursor)
-                .await?;
-
-            let page_info = history.page_info;
-            if page_info.has_next_page {
-                previous_cursor = page_info.start_cursor;
-                cursor = page_info.end_cursor;
-            } else {
-                let nodes = history.nodes.ok_or(anyhow!("missing nodes fiel"))?;
-                for node in nodes.iter() {
-                    if let Some(commit_node) = node {
-                        commits.push(commit_node.clone());
-                        count += 1;
-                        if count == count_limit {
-                            // stop scanning nodes
-                            break;
-                        };
-                    };
-                }
-
-                if count < count_limit && previous_cursor.is_some() {
-                    let previous_history = self
-                        .get_commit_history(owner.clone(), name.clone(), previous_cursor)
-                        .await?;
-                    let previous_nodes = previous_history
-                        .nodes
-                        .ok_or(anyhow!("missing nodes field in previous history"))?;
-                    for node in previous_nodes.iter() {
-                        if let Some(commit_node) = node {
-                            commits.push(commit_node.clone());
-                            count += 1;
-                            if count == count_limit {
-                                // stop scanning nodes
-                                break;
-                            };
-                        };
-                    }
-                }
+        self.get_tail_commits(owner, name, count, head).await
+    }
 
-                // no more cursor
-                break;
+    /// Return the commits of the head if its history fits in the page, or
+    /// fetch the tail of the history otherwise.
+    async fn get_tail_commits(
+        &self,
+        owner: String,
+        name: String,
+        count: i64,
+        head: CommitHead,
+    ) -> Result<Option<Vec<CommitEntry>>, GitHubError> {
+        match tail_cursor(&head.oid, head.history.total_count, count) {
+            None => Ok(into_commits(head.history)),
+            Some(cursor) => {
+                let tail = self
+                    .get_commit_history(owner, name, count, Some(cursor))
+                    .await?;
+                Ok(into_commits(tail.history))
             }
         }
+    }
 
-        if count > 0 {
-            Ok(Some(commits))
-        } else {
-            Ok(None)
+    /// Fetch the first commits of multiple repositories. The head of every
+    /// repository is fetched in a single request, so repositories with a
+    /// short history take no more request. The others take another request
+    /// for the tail of the history.
+    pub async fn get_first_commits_batch(
+        &self,
+        repositories: &[(String, String)],
+        count_limit: usize,
+    ) -> Result<Vec<Result<Option<Vec<CommitEntry>>, GitHubError>>, GitHubError> {
+        let count = count_limit as i64;
+        let mut batch = BatchQuery::default();
+        let selection = batch::commit_history_selection(count);
+        let aliases = repositories
+            .iter()
+            .map(|(owner, name)| batch.add_repository(owner, name, &selection))
+            .collect::<Vec<_>>();
+
+        let responses = self
+            .post_batch::<commit_history_query::CommitHistoryQueryRepository>(batch, &aliases)
+            .await?;
+
+        let mut results = Vec::new();
+
+        for (response, (owner, name)) in responses.into_iter().zip(repositories) {
+            results.push(match response.and_then(commit_head_of) {
+                Ok(head) => {
+                    self.get_tail_commits(owner.clone(), name.clone(), count, head)
+                        .await
+                }
+                Err(e) => Err(e),
+            });
         }
+
+        Ok(results)
     }
 }
 
@@ -343,17 +764,19 @@ mod tests {
         let expected_total_count = 105;
         let page_size = 20;
         let result = client
-            .get_stargazers_after_count(
+            .get_stargazers_page(
                 format!("akirak"),
                 format!("flake-templates"),
                 after_count,
                 expected_total_count,
                 page_size,
+                None,
             )
             .await;
         assert!(result.is_ok());
-        let (new_total_count, items) = result.unwrap();
-        assert_eq!(new_total_count, after_count + items.len() as i64);
+        let page = result.unwrap();
+        assert!(page.items.len() as i64 <= page_size);
+        assert!(page.total_count >= after_count + page.items.len() as i64);
     }
 
     #[tokio::test]
@@ -371,4 +794,35 @@ mod tests {
         let commits = result.unwrap().unwrap();
         assert_eq!(commits.len(), 3);
     }
+
+    #[test]
+    fn test_tail_cursor() {
+        let oid = "2c4f1d0b6a4bb1f7e0b7f3c8f0c1a9f3b6a7d8e9";
+        assert_eq!(tail_cursor(oid, 3, 5), None);
+        assert_eq!(tail_cursor(oid, 5, 5), None);
+        assert_eq!(tail_cursor(oid, 6, 5), Some(format!("{} 0", oid)));
+        assert_eq!(tail_cursor(oid, 1234, 5), Some(format!("{} 1228", oid)));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_first_commits_batch() {
+        let client = setup_client();
+
+        let result = client
+            .get_first_commits_batch(
+                &[
+                    (format!("akirak"), format!("twind.el")),
+                    (format!("akirak"), format!("no-such-repository")),
+                ],
+                3,
+            )
+            .await;
+
+        assert!(result.is_ok());
+        let results = result.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().as_ref().unwrap().len(), 3);
+        assert!(results[1].as_ref().is_err_and(|e| e.is_permanent()));
+    }
 }
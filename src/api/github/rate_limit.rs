@@ -0,0 +1,150 @@
+//! Bookkeeping of the GraphQL API rate limit.
+//!
+//! Every query requests the `rateLimit` field, and the reported budget is
+//! recorded here. Before sending a request, the client waits until the budget
+//! is reset if it is about to run out.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+
+/// The client stops sending requests when the remaining points fall below this
+/// value, until the budget is reset.
+const MIN_REMAINING_POINTS: i64 = 50;
+
+/// Extra time to wait after the reported reset time.
+const RESET_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub cost: i64,
+    pub remaining: i64,
+    pub reset_at: DateTime<Utc>,
+}
+
+impl RateLimit {
+    pub fn new(cost: i64, remaining: i64, reset_at: &str) -> Option<Self> {
+        match DateTime::parse_from_rfc3339(reset_at) {
+            Ok(reset_at) => Some(Self {
+                cost,
+                remaining,
+                reset_at: reset_at.with_timezone(&Utc),
+            }),
+            Err(e) => {
+                warn!(reset_at, error = %e, "failed to parse resetAt of the rate limit");
+                None
+            }
+        }
+    }
+}
+
+/// Implemented by response data types of queries that request `rateLimit`.
+pub trait RateLimited {
+    fn rate_limit(&self) -> Option<RateLimit>;
+}
+
+#[derive(Default)]
+struct State {
+    phase: Option<&'static str>,
+    points_by_phase: Vec<(&'static str, i64)>,
+    remaining: Option<i64>,
+    reset_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    state: Mutex<State>,
+}
+
+impl RateLimiter {
+    /// Set the name under which the cost of subsequent queries is accounted.
+    pub fn set_phase(&self, phase: &'static str) {
+        let mut state = self.state.lock().unwrap();
+        state.phase = Some(phase);
+        if !state.points_by_phase.iter().any(|(name, _)| *name == phase) {
+            state.points_by_phase.push((phase, 0));
+        }
+    }
+
+    pub fn record(&self, rate_limit: &RateLimit) {
+        let mut state = self.state.lock().unwrap();
+        let phase = state.phase.unwrap_or("other");
+        match state
+            .points_by_phase
+            .iter_mut()
+            .find(|(name, _)| *name == phase)
+        {
+            Some((_, points)) => *points += rate_limit.cost,
+            None => state.points_by_phase.push((phase, rate_limit.cost)),
+        }
+        state.remaining = Some(rate_limit.remaining);
+        state.reset_at = Some(rate_limit.reset_at);
+    }
+
+    /// Mark the budget as exhausted until the given time, e.g. when the
+    /// server rejects a request without returning the `rateLimit` field.
+    pub fn exhaust_until(&self, reset_at: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        state.remaining = Some(0);
+        state.reset_at = Some(reset_at);
+    }
+
+    /// Points spent in each phase, in the order the phases were started.
+    pub fn points_by_phase(&self) -> Vec<(&'static str, i64)> {
+        self.state.lock().unwrap().points_by_phase.clone()
+    }
+
+    pub fn remaining(&self) -> Option<i64> {
+        self.state.lock().unwrap().remaining
+    }
+
+    /// Sleep until the budget is reset if it is running low.
+    pub async fn wait(&self) {
+        let delay = {
+            let mut state = self.state.lock().unwrap();
+            match (state.remaining, state.reset_at) {
+                (Some(remaining), Some(reset_at)) if remaining < MIN_REMAINING_POINTS => {
+                    // Forget the budget, as it will be reported again by the
+                    // next response.
+                    state.remaining = None;
+                    (reset_at - Utc::now()).to_std().ok()
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(delay) = delay {
+            let delay = delay + RESET_GRACE_PERIOD;
+            info!(
+                seconds = delay.as_secs(),
+                "rate limit is running low, waiting until it is reset"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_points_by_phase() {
+        let limiter = RateLimiter::default();
+        let rate_limit = RateLimit::new(2, 4000, "2024-11-24T10:00:00Z").unwrap();
+
+        limiter.set_phase("repos");
+        limiter.record(&rate_limit);
+        limiter.record(&rate_limit);
+        limiter.set_phase("stargazers");
+        limiter.record(&rate_limit);
+
+        assert_eq!(
+            limiter.points_by_phase(),
+            vec![("repos", 4), ("stargazers", 2)]
+        );
+        assert_eq!(limiter.remaining(), Some(4000));
+    }
+}
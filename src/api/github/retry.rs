@@ -0,0 +1,66 @@
+//! Retry policy for transient failures.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts per request, including the first one.
+    pub max_attempts: u32,
+    /// Base delay before the first retry. It is doubled on every retry.
+    pub initial_delay: Duration,
+    /// Upper bound of the delay before a retry.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Return the delay before the given retry (starting from 1), using
+    /// exponential backoff with jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+        // Keep at least half of the delay, and randomize the rest
+        let half = exponential / 2;
+        half + half.mul_f64(random_fraction())
+    }
+}
+
+/// Return a pseudo-random number in [0, 1) without depending on a random
+/// number generator crate. `RandomState` is seeded differently on every call.
+fn random_fraction() -> f64 {
+    let value = RandomState::new().hash_one(0u8);
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(10),
+        };
+
+        for (retry, expected) in [(1, 2), (2, 4), (3, 8), (4, 10), (8, 10)] {
+            let expected = Duration::from_secs(expected);
+            let delay = policy.backoff(retry);
+            assert!(delay >= expected / 2 && delay <= expected, "{:?}", delay);
+        }
+    }
+}
@@ -4,16 +4,235 @@ use std::collections::HashMap;
 use chrono::{Duration, NaiveDate};
 use itertools::Itertools;
 use plotters::{
+    coord::{ranged1d::ValueFormatter, Shift},
     prelude::*,
-    style::text_anchor::{HPos, Pos, VPos},
+    style::{
+        register_font,
+        text_anchor::{HPos, Pos, VPos},
+    },
 };
 use serde::Deserialize;
 
-const FONT_FAMILY: &str = "sans-serif";
+use crate::rate::{RatePeriod, StarRate};
+
+mod chartjs;
+mod vega_lite;
+
+/// Output format of a chart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChartFormat {
+    /// SVG image
+    Svg,
+    /// PNG image
+    Png,
+    /// Chart.js configuration in JSON, e.g. for QuickChart
+    Chartjs,
+    /// Vega-Lite specification in JSON, with the data inlined
+    VegaLite,
+}
+
+/// Colour theme of a chart image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChartTheme {
+    /// Dark text on a white background
+    Light,
+    /// Light text on a dark background, matching the dark theme of GitHub
+    Dark,
+    /// Grey text on a transparent background, legible on both light and dark
+    /// pages (SVG only)
+    Transparent,
+}
+
+/// Colours of the elements of a chart image.
+struct ThemeColors {
+    background: RGBAColor,
+    foreground: RGBColor,
+}
+
+impl ChartTheme {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+            Self::Transparent => "transparent",
+        }
+    }
+
+    fn colors(self) -> ThemeColors {
+        match self {
+            Self::Light => ThemeColors {
+                background: WHITE.to_rgba(),
+                foreground: BLACK,
+            },
+            Self::Dark => ThemeColors {
+                background: RGBColor(0x0d, 0x11, 0x17).to_rgba(),
+                foreground: RGBColor(0xe6, 0xed, 0xf3),
+            },
+            Self::Transparent => ThemeColors {
+                background: TRANSPARENT,
+                foreground: RGBColor(0x80, 0x80, 0x80),
+            },
+        }
+    }
+}
+
+/// Compound extension of Vega-Lite specifications, kept whole in
+/// [`themed_path`].
+const VEGA_LITE_EXTENSION: &str = ".vl.json";
+
+/// Return the path for a variant of a chart in the theme, by inserting the
+/// name of the theme before the extension, e.g. `chart-dark.svg`. Only the last
+/// extension is considered, except for `.vl.json`, and the leading dot of a
+/// dotfile is not an extension.
+pub fn themed_path(path: &str, theme: ChartTheme) -> String {
+    let file_start = path.rfind('/').map_or(0, |i| i + 1);
+    let file = &path[file_start..];
+    let extension_start = file
+        .len()
+        .checked_sub(VEGA_LITE_EXTENSION.len())
+        .filter(|&i| {
+            i > 0
+                && file
+                    .get(i..)
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(VEGA_LITE_EXTENSION))
+        })
+        .or_else(|| file.rfind('.').filter(|&i| i > 0));
+    match extension_start {
+        Some(i) => format!(
+            "{}-{}{}",
+            &path[..file_start + i],
+            theme.name(),
+            &path[file_start + i..]
+        ),
+        None => format!("{}-{}", path, theme.name()),
+    }
+}
+
+impl ChartFormat {
+    /// Infer the format from the extension of a path.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.to_lowercase();
+        if path.ends_with(".svg") {
+            Some(Self::Svg)
+        } else if path.ends_with(".png") {
+            Some(Self::Png)
+        } else if path.ends_with(VEGA_LITE_EXTENSION) {
+            Some(Self::VegaLite)
+        } else if path.ends_with(".json") {
+            Some(Self::Chartjs)
+        } else {
+            None
+        }
+    }
+}
+
+/// How the series of the language and repository charts are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartStyle {
+    /// A line for each series
+    #[default]
+    Lines,
+    /// Areas stacked on top of each other, with the total at the top
+    Stacked,
+    /// Stacked areas of the share of each series in percent
+    Percent,
+}
+
+/// Options of the layout and the text of a chart, which can be set in the
+/// `[chart]` table of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChartOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Title of the chart, which defaults to a title for the chart type. An
+    /// empty title hides it.
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    /// Font family of the text. Images are rendered with the bundled font
+    /// regardless of the family, but SVG viewers use the family if available.
+    pub font_family: String,
+    pub title_font_size: u32,
+    /// Font size of the subtitle, axis labels and legends.
+    pub label_font_size: u32,
+    pub margin_top: u32,
+    pub margin_right: u32,
+    pub margin_bottom: u32,
+    pub margin_left: u32,
+    /// Maximum number of labels on the x axis.
+    pub x_labels: usize,
+    /// Maximum number of labels on the y axis.
+    pub y_labels: usize,
+    pub style: ChartStyle,
+}
+
+impl Default for ChartOptions {
+    fn default() -> Self {
+        Self {
+            width: 500,
+            height: 250,
+            title: None,
+            subtitle: None,
+            font_family: FONT_FAMILY.to_string(),
+            title_font_size: 14,
+            label_font_size: 12,
+            margin_top: 10,
+            margin_right: 30,
+            margin_bottom: 10,
+            margin_left: 10,
+            x_labels: 5,
+            y_labels: 10,
+            style: ChartStyle::Lines,
+        }
+    }
+}
 
-const CHART_CAPTION_FONT_SIZE: i32 = 14;
+impl ChartOptions {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Return the title, or `None` if it is hidden.
+    fn title<'a>(&'a self, default: &'a str) -> Option<&'a str> {
+        Some(self.title.as_deref().unwrap_or(default)).filter(|title| !title.is_empty())
+    }
+}
+
+const LANGUAGE_CHART_TITLE: &str = "Number of stargazers by language";
+
+const REPOSITORY_CHART_TITLE: &str = "Number of stargazers by repository";
+
+const TOTAL_CHART_TITLE: &str = "Total number of stargazers";
 
-const LABEL_FONT_SIZE: i32 = 12;
+const NET_CHART_TITLE: &str = "Net number of stars";
+
+/// Colours of repositories, assigned in the order of their first appearance.
+const REPOSITORY_COLORS: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+
+/// Colour of the line of the total star history.
+const TOTAL_COLOR: &str = "#000000";
+
+/// Colour of the bars of new stars.
+const RATE_COLOR: &str = "#4e79a7";
+
+/// Colour of the moving average of new stars.
+const AVERAGE_COLOR: &str = "#f28e2b";
+
+const RATE_LABEL: &str = "New stars";
+
+/// Colour of languages without one in `colors.json`.
+const FALLBACK_COLOR: &str = "#ff0000";
+
+/// Default font family.
+const FONT_FAMILY: &str = "sans-serif";
+
+/// Font used for all text of images, so they are rendered the same regardless
+/// of the fonts installed on the machine.
+const FONT_DATA: &[u8] = include_bytes!("fonts/DejaVuSans.ttf");
 
 fn parse_hex_color(hex: &str) -> anyhow::Result<RGBColor> {
     let hex = hex.trim_start_matches('#');
@@ -41,63 +260,424 @@ fn load_github_colors() -> anyhow::Result<HashMap<String, ColorEntry>> {
     Ok(data)
 }
 
-pub fn draw_star_history_by_language(
+/// Register the bundled font as the family, so text is measured and rasterized
+/// with it.
+fn register_bundled_font(family: &str) -> anyhow::Result<()> {
+    register_font(family, FontStyle::Normal, FONT_DATA)
+        .map_err(|_| anyhow!("Failed to load the bundled font"))
+}
+
+/// What each line of a chart with multiple series stands for.
+#[derive(Debug, Clone, Copy)]
+enum SeriesKind {
+    Language,
+    Repository,
+}
+
+impl SeriesKind {
+    fn title(self) -> &'static str {
+        match self {
+            Self::Language => LANGUAGE_CHART_TITLE,
+            Self::Repository => REPOSITORY_CHART_TITLE,
+        }
+    }
+
+    /// Name of the field of the series in the data of JSON formats.
+    fn field(self) -> &'static str {
+        match self {
+            Self::Language => "language",
+            Self::Repository => "repository",
+        }
+    }
+
+    fn series(self, vec: &[(NaiveDate, String, i64)]) -> anyhow::Result<Vec<Series>> {
+        match self {
+            Self::Language => language_series(vec),
+            Self::Repository => Ok(repository_series(vec)),
+        }
+    }
+}
+
+/// A line of a chart with multiple series.
+struct Series {
+    name: String,
+    /// Hex colour of the line.
+    color: String,
+}
+
+/// Return the names of the series in the order of their first appearance.
+fn unique_series(vec: &[(NaiveDate, String, i64)]) -> Vec<&str> {
+    vec.iter()
+        .map(|(_, name, _)| name.as_str())
+        .unique()
+        .collect()
+}
+
+/// Return the series of languages, with the colours of GitHub.
+fn language_series(vec: &[(NaiveDate, String, i64)]) -> anyhow::Result<Vec<Series>> {
+    let color_map = load_github_colors()?;
+    Ok(unique_series(vec)
+        .into_iter()
+        .map(|language| Series {
+            name: language.to_string(),
+            color: color_map
+                .get(language)
+                .and_then(|ent| ent.color.clone())
+                .unwrap_or_else(|| FALLBACK_COLOR.to_string()),
+        })
+        .collect())
+}
+
+/// Return the series of repositories, with colours from a fixed palette.
+fn repository_series(vec: &[(NaiveDate, String, i64)]) -> Vec<Series> {
+    unique_series(vec)
+        .into_iter()
+        .zip(REPOSITORY_COLORS.iter().cycle())
+        .map(|(repository, color)| Series {
+            name: repository.to_string(),
+            color: color.to_string(),
+        })
+        .collect()
+}
+
+/// Align the series on the dates of all entries, carrying forward the last
+/// value of each series. The values of each date are in the order of `series`.
+fn align_series(vec: &[(NaiveDate, String, i64)], series: &[Series]) -> Vec<(NaiveDate, Vec<i64>)> {
+    let index = series
+        .iter()
+        .enumerate()
+        .map(|(i, series)| (series.name.as_str(), i))
+        .collect::<HashMap<_, _>>();
+    let mut current = vec![0; series.len()];
+    let mut rows: Vec<(NaiveDate, Vec<i64>)> = Vec::new();
+
+    for (date, name, value) in vec {
+        if let Some(&i) = index.get(name.as_str()) {
+            current[i] = *value;
+        }
+        match rows.last_mut() {
+            Some((last_date, values)) if last_date == date => values.clone_from(&current),
+            _ => rows.push((*date, current.clone())),
+        }
+    }
+
+    rows
+}
+
+/// Return the share of each value in percent.
+fn percentages(values: &[i64]) -> Vec<f64> {
+    let total = values.iter().sum::<i64>();
+    values
+        .iter()
+        .map(|&value| {
+            if total == 0 {
+                0.0
+            } else {
+                value as f64 * 100.0 / total as f64
+            }
+        })
+        .collect()
+}
+
+/// Check if the theme can be applied to the format.
+pub fn check_theme(format: ChartFormat, theme: ChartTheme) -> anyhow::Result<()> {
+    match (format, theme) {
+        (ChartFormat::Png, ChartTheme::Transparent) => Err(anyhow!(
+            "The transparent theme is not supported for PNG images"
+        )),
+        (
+            ChartFormat::Chartjs | ChartFormat::VegaLite,
+            ChartTheme::Dark | ChartTheme::Transparent,
+        ) => Err(anyhow!("Themes are only supported for images")),
+        _ => Ok(()),
+    }
+}
+
+/// Save the star history by language to the path in the format.
+pub fn save_star_history_by_language(
     vec: Vec<(NaiveDate, String, i64)>,
     path: &str,
+    format: ChartFormat,
+    theme: ChartTheme,
+    options: &ChartOptions,
 ) -> anyhow::Result<()> {
-    let drawing_area = plotters::prelude::SVGBackend::new(path, (500, 250)).into_drawing_area();
-    let root = drawing_area;
+    save_star_history_by_series(vec, SeriesKind::Language, path, format, theme, options)
+}
 
-    let from_date = vec.first().unwrap().0 - Duration::days(1);
-    let to_date = vec.last().unwrap().0 + Duration::days(1);
+/// Save the star history by repository to the path in the format.
+pub fn save_star_history_by_repository(
+    vec: Vec<(NaiveDate, String, i64)>,
+    path: &str,
+    format: ChartFormat,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    save_star_history_by_series(vec, SeriesKind::Repository, path, format, theme, options)
+}
 
-    let max_value = vec.iter().map(|(_, _, n)| n.clone()).max().unwrap();
+fn save_star_history_by_series(
+    vec: Vec<(NaiveDate, String, i64)>,
+    kind: SeriesKind,
+    path: &str,
+    format: ChartFormat,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    check_theme(format, theme)?;
+    let series = kind.series(&vec)?;
+    match format {
+        ChartFormat::Svg => draw_star_history_by_series(
+            SVGBackend::new(path, options.size()).into_drawing_area(),
+            vec,
+            &series,
+            kind.title(),
+            theme,
+            options,
+        ),
+        ChartFormat::Png => draw_star_history_by_series(
+            BitMapBackend::new(path, options.size()).into_drawing_area(),
+            vec,
+            &series,
+            kind.title(),
+            theme,
+            options,
+        ),
+        ChartFormat::Chartjs => write_json(
+            path,
+            &chartjs::star_history_by_series(&vec, &series, kind.title(), options),
+        ),
+        ChartFormat::VegaLite => write_json(
+            path,
+            &vega_lite::star_history_by_series(&vec, &series, kind, options),
+        ),
+    }
+}
 
-    root.fill(&WHITE)?;
+/// Save the total star history to the path in the format.
+pub fn save_total_star_history(
+    vec: Vec<(NaiveDate, i64)>,
+    path: &str,
+    format: ChartFormat,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    save_single_star_history(vec, TOTAL_CHART_TITLE, path, format, theme, options)
+}
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption(
-            "Number of stargazers by language",
-            (FONT_FAMILY, CHART_CAPTION_FONT_SIZE),
-        )
-        .x_label_area_size(20)
-        .y_label_area_size(20)
-        .margin(10)
-        .margin_right(30)
-        .build_cartesian_2d(from_date..to_date, 0..max_value + 50)?;
+/// Save the history of the total of the star counts to the path in the format.
+pub fn save_net_star_history(
+    vec: Vec<(NaiveDate, i64)>,
+    path: &str,
+    format: ChartFormat,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    save_single_star_history(vec, NET_CHART_TITLE, path, format, theme, options)
+}
+
+fn save_single_star_history(
+    vec: Vec<(NaiveDate, i64)>,
+    default_title: &str,
+    path: &str,
+    format: ChartFormat,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    check_theme(format, theme)?;
+    match format {
+        ChartFormat::Svg => draw_total_star_history(
+            SVGBackend::new(path, options.size()).into_drawing_area(),
+            vec,
+            default_title,
+            theme,
+            options,
+        ),
+        ChartFormat::Png => draw_total_star_history(
+            BitMapBackend::new(path, options.size()).into_drawing_area(),
+            vec,
+            default_title,
+            theme,
+            options,
+        ),
+        ChartFormat::Chartjs => write_json(
+            path,
+            &chartjs::total_star_history(&vec, default_title, options),
+        ),
+        ChartFormat::VegaLite => write_json(
+            path,
+            &vega_lite::total_star_history(&vec, default_title, options),
+        ),
+    }
+}
+
+/// Save the number of new stars per period to the path in the format.
+pub fn save_star_rate(
+    rate: &StarRate,
+    path: &str,
+    format: ChartFormat,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    check_theme(format, theme)?;
+    match format {
+        ChartFormat::Svg => draw_star_rate(
+            SVGBackend::new(path, options.size()).into_drawing_area(),
+            rate,
+            theme,
+            options,
+        ),
+        ChartFormat::Png => draw_star_rate(
+            BitMapBackend::new(path, options.size()).into_drawing_area(),
+            rate,
+            theme,
+            options,
+        ),
+        ChartFormat::Chartjs => write_json(path, &chartjs::star_rate(rate, options)),
+        ChartFormat::VegaLite => write_json(path, &vega_lite::star_rate(rate, options)),
+    }
+}
+
+fn rate_chart_title(period: RatePeriod) -> String {
+    format!("New stargazers per {}", period.name())
+}
+
+fn moving_average_label(window: usize, period: RatePeriod) -> String {
+    format!("{window}-{} moving average", period.name())
+}
 
+fn write_json(path: &str, value: &serde_json::Value) -> anyhow::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+fn configure_mesh<DB: DrawingBackend, Y>(
+    chart: &mut ChartContext<DB, Cartesian2d<RangedDate<NaiveDate>, Y>>,
+    colors: &ThemeColors,
+    options: &ChartOptions,
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+    Y: Ranged + ValueFormatter<Y::ValueType>,
+{
     chart
         .configure_mesh()
         .disable_x_mesh()
-        .x_labels(5)
+        .x_labels(options.x_labels)
+        .y_labels(options.y_labels)
         .max_light_lines(2)
+        .axis_style(colors.foreground)
+        .bold_line_style(colors.foreground.mix(0.2))
+        .light_line_style(colors.foreground.mix(0.1))
+        .label_style(
+            (options.font_family.as_str(), options.label_font_size)
+                .into_font()
+                .color(&colors.foreground),
+        )
         .draw()?;
+    Ok(())
+}
 
-    let languages = vec
-        .iter()
-        .map(|(_, lang, _)| lang)
-        .unique()
-        .collect::<Vec<_>>();
+fn draw_legend<'a, DB: DrawingBackend + 'a, CT: CoordTranslate>(
+    chart: &mut ChartContext<'a, DB, CT>,
+    colors: &ThemeColors,
+    options: &ChartOptions,
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(colors.background)
+        .label_font(
+            (options.font_family.as_str(), options.label_font_size)
+                .into_font()
+                .color(&colors.foreground),
+        )
+        .draw()?;
+    Ok(())
+}
 
-    let centered = Pos::new(HPos::Center, VPos::Top);
+/// Fill the background, draw the titles, and return the area for the chart.
+fn chart_area<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    default_title: &str,
+    colors: &ThemeColors,
+    options: &ChartOptions,
+) -> anyhow::Result<DrawingArea<DB, Shift>>
+where
+    DB::ErrorType: 'static,
+{
+    register_bundled_font(&options.font_family)?;
 
-    let color_map = load_github_colors()?;
+    root.fill(&colors.background)?;
+
+    let mut area = root.margin(
+        options.margin_top,
+        options.margin_bottom,
+        options.margin_left,
+        options.margin_right,
+    );
+    if let Some(title) = options.title(default_title) {
+        area = area.titled(
+            title,
+            (options.font_family.as_str(), options.title_font_size)
+                .into_font()
+                .color(&colors.foreground),
+        )?;
+    }
+    if let Some(subtitle) = options.subtitle.as_deref() {
+        area = area.titled(
+            subtitle,
+            (options.font_family.as_str(), options.label_font_size)
+                .into_font()
+                .color(&colors.foreground),
+        )?;
+    }
+
+    Ok(area)
+}
+
+fn draw_star_history_by_series<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    vec: Vec<(NaiveDate, String, i64)>,
+    series: &[Series],
+    default_title: &str,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    if options.style != ChartStyle::Lines {
+        return draw_stacked_star_history(root, vec, series, default_title, theme, options);
+    }
+
+    let colors = theme.colors();
+
+    let from_date = vec.first().unwrap().0 - Duration::days(1);
+    let to_date = vec.last().unwrap().0 + Duration::days(1);
+
+    let max_value = vec.iter().map(|(_, _, n)| n.clone()).max().unwrap();
+
+    let area = chart_area(&root, default_title, &colors, options)?;
+
+    let mut chart = ChartBuilder::on(&area)
+        .x_label_area_size(options.label_font_size + 8)
+        .y_label_area_size(options.label_font_size + 8)
+        .build_cartesian_2d(from_date..to_date, 0..max_value + 50)?;
+
+    configure_mesh(&mut chart, &colors, options)?;
+
+    let centered = Pos::new(HPos::Center, VPos::Top);
 
-    for &language in languages.iter() {
-        let color = match color_map
-            .get(language)
-            .map(|ent| ent.color.clone())
-            .flatten()
-        {
-            Some(hex_color) => parse_hex_color(hex_color.as_str())?,
-            // fallback
-            None => RED,
-        };
+    for Series { name, color } in series.iter() {
+        let color = parse_hex_color(color)?;
 
         let items = vec
             .iter()
-            .filter_map(|(date, lang, value)| {
-                if language.eq(lang) {
+            .filter_map(|(date, series_name, value)| {
+                if name.eq(series_name) {
                     Some((date.clone(), value.clone()))
                 } else {
                     None
@@ -105,63 +685,340 @@ pub fn draw_star_history_by_language(
             })
             .collect::<Vec<_>>();
 
-        let (x, y) = items.get((items.len() / 2) + 1).unwrap().clone();
-        let label_style = TextStyle::from((FONT_FAMILY, LABEL_FONT_SIZE).into_font())
-            .pos(centered)
-            .color(&color);
-        chart
-            .draw_series([EmptyElement::at((x, y))
-                + Text::new(language.to_string(), (-10, -15), &label_style)])?;
+        // A series may only have a single point, e.g. a repository starred once
+        let (x, y) = items
+            .get((items.len() / 2) + 1)
+            .or(items.last())
+            .unwrap()
+            .clone();
+        let label_style =
+            TextStyle::from((options.font_family.as_str(), options.label_font_size).into_font())
+                .pos(centered)
+                .color(&color);
+        chart.draw_series([
+            EmptyElement::at((x, y)) + Text::new(name.to_string(), (-10, -15), &label_style)
+        ])?;
 
         chart
             .draw_series(LineSeries::new(items, color.stroke_width(1)))?
-            .label(language)
+            .label(name)
             .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 15, y)], color));
     }
 
-    chart
-        .configure_series_labels()
-        .position(SeriesLabelPosition::UpperLeft)
-        .background_style(WHITE)
-        .draw()?;
+    draw_legend(&mut chart, &colors, options)?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+/// Draw the series as stacked areas, either of the values or of the shares in
+/// percent, depending on the style.
+fn draw_stacked_star_history<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    vec: Vec<(NaiveDate, String, i64)>,
+    series: &[Series],
+    default_title: &str,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let colors = theme.colors();
+    let percent = options.style == ChartStyle::Percent;
+
+    // The top of the area of each series is the sum of the values up to it
+    let rows = align_series(&vec, series)
+        .into_iter()
+        .map(|(date, values)| {
+            let values = if percent {
+                percentages(&values)
+            } else {
+                values.iter().map(|&value| value as f64).collect()
+            };
+            let tops = values
+                .iter()
+                .scan(0.0, |sum, value| {
+                    *sum += value;
+                    Some(*sum)
+                })
+                .collect::<Vec<_>>();
+            (date, tops)
+        })
+        .collect::<Vec<_>>();
+
+    let from_date = rows.first().unwrap().0 - Duration::days(1);
+    let to_date = rows.last().unwrap().0 + Duration::days(1);
+
+    let max_value = if percent {
+        100.0
+    } else {
+        rows.iter()
+            .filter_map(|(_, tops)| tops.last().copied())
+            .fold(0.0, f64::max)
+            + 50.0
+    };
+
+    let area = chart_area(&root, default_title, &colors, options)?;
+
+    let mut chart = ChartBuilder::on(&area)
+        .x_label_area_size(options.label_font_size + 8)
+        .y_label_area_size(options.label_font_size + 8)
+        .build_cartesian_2d(from_date..to_date, 0.0..max_value)?;
+
+    configure_mesh(&mut chart, &colors, options)?;
+
+    // The upper areas are drawn first, so the lower ones are painted over them
+    for (i, Series { name, color }) in series.iter().enumerate().rev() {
+        let color = parse_hex_color(color)?;
+        let points = rows
+            .iter()
+            .map(|(date, tops)| (*date, tops[i]))
+            .collect::<Vec<_>>();
+
+        chart
+            .draw_series(AreaSeries::new(points, 0.0, color.filled()))?
+            .label(name)
+            .legend(move |(x, y)| Rectangle::new([(x, y - 4), (x + 15, y + 4)], color.filled()));
+    }
+
+    draw_legend(&mut chart, &colors, options)?;
 
     root.present()?;
 
     Ok(())
 }
 
-pub fn draw_total_star_history(vec: Vec<(NaiveDate, i64)>, path: &str) -> anyhow::Result<()> {
-    let drawing_area = plotters::prelude::SVGBackend::new(path, (500, 250)).into_drawing_area();
-    let root = drawing_area;
+pub fn draw_total_star_history<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    vec: Vec<(NaiveDate, i64)>,
+    default_title: &str,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let colors = theme.colors();
 
     let from_date = vec.first().unwrap().0 - Duration::days(1);
     let to_date = vec.last().unwrap().0 + Duration::days(1);
 
-    let max_value = vec.last().unwrap().1;
+    let max_value = vec.iter().map(|(_, n)| *n).max().unwrap();
 
-    root.fill(&WHITE)?;
+    let area = chart_area(&root, default_title, &colors, options)?;
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption(
-            "Total number of stargazers",
-            (FONT_FAMILY, CHART_CAPTION_FONT_SIZE),
-        )
-        .x_label_area_size(20)
-        .y_label_area_size(20)
-        .margin(10)
-        .margin_right(30)
+    let mut chart = ChartBuilder::on(&area)
+        .x_label_area_size(options.label_font_size + 8)
+        .y_label_area_size(options.label_font_size + 8)
         .build_cartesian_2d(from_date..to_date, 0..max_value + 50)?;
 
+    configure_mesh(&mut chart, &colors, options)?;
+
+    chart.draw_series(LineSeries::new(vec, &colors.foreground))?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+pub fn draw_star_rate<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    rate: &StarRate,
+    theme: ChartTheme,
+    options: &ChartOptions,
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let colors = theme.colors();
+    let period = rate.period;
+
+    let from_date = rate.counts.first().unwrap().0 - Duration::days(1);
+    let to_date = period.next(rate.counts.last().unwrap().0) + Duration::days(1);
+
+    let max_value = rate.counts.iter().map(|(_, n)| *n).max().unwrap();
+
+    let area = chart_area(&root, &rate_chart_title(period), &colors, options)?;
+
+    let mut chart = ChartBuilder::on(&area)
+        .x_label_area_size(options.label_font_size + 8)
+        .y_label_area_size(options.label_font_size + 8)
+        .build_cartesian_2d(from_date..to_date, 0.0..(max_value as f64 * 1.1).max(1.0))?;
+
+    configure_mesh(&mut chart, &colors, options)?;
+
+    // Each bar spans its period
+    let bar_color = parse_hex_color(RATE_COLOR)?;
     chart
-        .configure_mesh()
-        .disable_x_mesh()
-        .x_labels(5)
-        .max_light_lines(2)
-        .draw()?;
+        .draw_series(rate.counts.iter().map(|&(start, count)| {
+            Rectangle::new(
+                [(start, count as f64), (period.next(start), 0.0)],
+                bar_color.filled(),
+            )
+        }))?
+        .label(RATE_LABEL)
+        .legend(move |(x, y)| Rectangle::new([(x, y - 4), (x + 15, y + 4)], bar_color.filled()));
 
-    chart.draw_series(LineSeries::new(vec, &BLACK))?;
+    if let Some((window, average)) = &rate.average {
+        let line_color = parse_hex_color(AVERAGE_COLOR)?;
+        chart
+            .draw_series(LineSeries::new(
+                average.iter().copied(),
+                line_color.stroke_width(2),
+            ))?
+            .label(moving_average_label(*window, period))
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 15, y)], line_color));
+
+        draw_legend(&mut chart, &colors, options)?;
+    }
 
     root.present()?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ChartFormat::from_path("total.svg"), Some(ChartFormat::Svg));
+        assert_eq!(ChartFormat::from_path("total.PNG"), Some(ChartFormat::Png));
+        assert_eq!(
+            ChartFormat::from_path("total.vl.json"),
+            Some(ChartFormat::VegaLite)
+        );
+        assert_eq!(
+            ChartFormat::from_path("total.json"),
+            Some(ChartFormat::Chartjs)
+        );
+        assert_eq!(ChartFormat::from_path("total"), None);
+    }
+
+    #[test]
+    fn test_repository_series() {
+        let date = NaiveDate::from_ymd_opt(2024, 9, 21).unwrap();
+        let vec = (0..=REPOSITORY_COLORS.len())
+            .map(|i| (date, format!("owner/repo{i}"), 1))
+            .collect::<Vec<_>>();
+        let series = repository_series(&vec);
+        assert_eq!(series.len(), REPOSITORY_COLORS.len() + 1);
+        assert_eq!(series[0].name, "owner/repo0");
+        assert_eq!(series[0].color, REPOSITORY_COLORS[0]);
+        assert_eq!(series[REPOSITORY_COLORS.len()].color, REPOSITORY_COLORS[0]);
+    }
+
+    #[test]
+    fn test_draw_stacked_styles() {
+        let vec = vec![
+            (date("2024-09-21"), "Rust".to_string(), 1),
+            (date("2024-09-22"), "Go".to_string(), 2),
+            (date("2024-09-23"), "Rust".to_string(), 3),
+        ];
+        let series = SeriesKind::Language.series(&vec).unwrap();
+
+        assert_eq!(
+            align_series(&vec, &series),
+            vec![
+                (date("2024-09-21"), vec![1, 0]),
+                (date("2024-09-22"), vec![1, 2]),
+                (date("2024-09-23"), vec![3, 2]),
+            ]
+        );
+        assert_eq!(percentages(&[3, 1]), vec![75.0, 25.0]);
+        assert_eq!(percentages(&[0, 0]), vec![0.0, 0.0]);
+
+        for style in [ChartStyle::Stacked, ChartStyle::Percent] {
+            let mut svg = String::new();
+            draw_star_history_by_series(
+                SVGBackend::with_string(&mut svg, (500, 250)).into_drawing_area(),
+                vec.clone(),
+                &series,
+                SeriesKind::Language.title(),
+                ChartTheme::Light,
+                &ChartOptions {
+                    style,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert!(svg.contains("Go"));
+        }
+    }
+
+    #[test]
+    fn test_draw_star_rate() {
+        let rate = StarRate::new(
+            &[(date("2024-09-21"), 1), (date("2024-10-08"), 4)],
+            RatePeriod::Week,
+            NonZeroUsize::new(2),
+        );
+        let mut svg = String::new();
+        draw_star_rate(
+            SVGBackend::with_string(&mut svg, (500, 250)).into_drawing_area(),
+            &rate,
+            ChartTheme::Light,
+            &ChartOptions::default(),
+        )
+        .unwrap();
+        assert!(svg.contains("New stargazers per week"));
+        assert!(svg.contains("2-week moving average"));
+    }
+
+    #[test]
+    fn test_themed_path() {
+        assert_eq!(
+            themed_path("docs/total.svg", ChartTheme::Dark),
+            "docs/total-dark.svg"
+        );
+        assert_eq!(
+            themed_path("./total.vl.json", ChartTheme::Light),
+            "./total-light.vl.json"
+        );
+        assert_eq!(themed_path("total", ChartTheme::Dark), "total-dark");
+        assert_eq!(
+            themed_path("my.stars.svg", ChartTheme::Dark),
+            "my.stars-dark.svg"
+        );
+        assert_eq!(
+            themed_path("my.stars.vl.json", ChartTheme::Dark),
+            "my.stars-dark.vl.json"
+        );
+        assert_eq!(
+            themed_path("docs/.chart.svg", ChartTheme::Dark),
+            "docs/.chart-dark.svg"
+        );
+        assert_eq!(themed_path(".chart", ChartTheme::Dark), ".chart-dark");
+        assert_eq!(
+            themed_path("v1.2/total", ChartTheme::Dark),
+            "v1.2/total-dark"
+        );
+    }
+
+    #[test]
+    fn test_draw_dark_theme() {
+        let mut svg = String::new();
+        draw_total_star_history(
+            SVGBackend::with_string(&mut svg, (500, 250)).into_drawing_area(),
+            vec![(date("2024-09-21"), 1), (date("2024-09-22"), 3)],
+            TOTAL_CHART_TITLE,
+            ChartTheme::Dark,
+            &ChartOptions {
+                subtitle: Some("Subtitle".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(svg.contains("#0D1117"));
+        assert!(svg.contains(TOTAL_CHART_TITLE));
+        assert!(svg.contains("Subtitle"));
+
+        assert!(check_theme(ChartFormat::Png, ChartTheme::Transparent).is_err());
+        assert!(check_theme(ChartFormat::Chartjs, ChartTheme::Light).is_ok());
+    }
+}
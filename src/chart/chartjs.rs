@@ -0,0 +1,273 @@
+//! Chart.js configurations of the charts, which can be rendered by QuickChart.
+//!
+//! The configurations target Chart.js 4, with a time scale on the x axis. Each
+//! point is given as an object with the date (`YYYY-MM-DD`) and the value. The
+//! size of the chart is up to the renderer, e.g. the width and height
+//! parameters of QuickChart.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{
+    align_series, moving_average_label, percentages, rate_chart_title, ChartOptions, ChartStyle,
+    Series, AVERAGE_COLOR, RATE_COLOR, RATE_LABEL, TOTAL_COLOR,
+};
+use crate::rate::StarRate;
+
+pub fn star_history_by_series(
+    vec: &[(NaiveDate, String, i64)],
+    series: &[Series],
+    default_title: &str,
+    options: &ChartOptions,
+) -> Value {
+    if options.style != ChartStyle::Lines {
+        return stacked_star_history(vec, series, default_title, options);
+    }
+
+    let datasets = series
+        .iter()
+        .map(|Series { name, color }| {
+            let points = vec
+                .iter()
+                .filter(|(_, series_name, _)| series_name == name)
+                .map(|(date, _, value)| point(date, *value))
+                .collect();
+            dataset(name, color, points)
+        })
+        .collect();
+
+    config(options, default_title, datasets, true)
+}
+
+/// Stack the series on the y axis, with each area filled down to the one
+/// below it. The shares in percent are computed upfront, as Chart.js has no
+/// option to normalize the stack.
+fn stacked_star_history(
+    vec: &[(NaiveDate, String, i64)],
+    series: &[Series],
+    default_title: &str,
+    options: &ChartOptions,
+) -> Value {
+    let percent = options.style == ChartStyle::Percent;
+    let rows = align_series(vec, series);
+    let shares = rows
+        .iter()
+        .map(|(_, values)| percentages(values))
+        .collect::<Vec<_>>();
+
+    let datasets = series
+        .iter()
+        .enumerate()
+        .map(|(i, Series { name, color })| {
+            let points = rows
+                .iter()
+                .zip(shares.iter())
+                .map(|((date, values), shares)| {
+                    if percent {
+                        point(date, (shares[i] * 100.0).round() / 100.0)
+                    } else {
+                        point(date, values[i])
+                    }
+                })
+                .collect();
+            let mut dataset = dataset(name, color, points);
+            dataset["fill"] = json!(if i == 0 { "origin" } else { "-1" });
+            dataset
+        })
+        .collect();
+
+    let mut config = config(options, default_title, datasets, true);
+    let y = &mut config["options"]["scales"]["y"];
+    y["stacked"] = json!(true);
+    if percent {
+        y["max"] = json!(100);
+        y["title"]["text"] = json!("Share of stars (%)");
+    }
+    config
+}
+
+pub fn total_star_history(
+    vec: &[(NaiveDate, i64)],
+    default_title: &str,
+    options: &ChartOptions,
+) -> Value {
+    let points = vec
+        .iter()
+        .map(|(date, value)| point(date, *value))
+        .collect();
+    config(
+        options,
+        default_title,
+        vec![dataset("Stargazers", TOTAL_COLOR, points)],
+        false,
+    )
+}
+
+/// Bars of the new stars of each period, with the moving average drawn as a
+/// line over them.
+pub fn star_rate(rate: &StarRate, options: &ChartOptions) -> Value {
+    let bars = rate
+        .counts
+        .iter()
+        .map(|(date, count)| point(date, *count))
+        .collect::<Vec<_>>();
+    let mut datasets = vec![json!({
+        "type": "bar",
+        "label": RATE_LABEL,
+        "data": bars,
+        "backgroundColor": RATE_COLOR,
+        "barPercentage": 1.0,
+        "categoryPercentage": 1.0,
+    })];
+    if let Some((window, average)) = &rate.average {
+        let points = average
+            .iter()
+            .map(|(date, value)| point(date, (value * 100.0).round() / 100.0))
+            .collect();
+        let mut line = dataset(
+            &moving_average_label(*window, rate.period),
+            AVERAGE_COLOR,
+            points,
+        );
+        line["borderWidth"] = json!(2);
+        datasets.push(line);
+    }
+
+    let mut config = config(
+        options,
+        &rate_chart_title(rate.period),
+        datasets,
+        rate.average.is_some(),
+    );
+    let scales = &mut config["options"]["scales"];
+    scales["x"]["time"]["unit"] = json!(rate.period.name());
+    scales["x"]["offset"] = json!(true);
+    scales["y"]["title"]["text"] = json!(RATE_LABEL);
+    config
+}
+
+fn point(date: &NaiveDate, value: impl Serialize) -> Value {
+    json!({ "x": date.format("%Y-%m-%d").to_string(), "y": value })
+}
+
+fn dataset(label: &str, color: &str, points: Vec<Value>) -> Value {
+    json!({
+        "label": label,
+        "data": points,
+        "borderColor": color,
+        "backgroundColor": color,
+        "borderWidth": 1,
+        "pointRadius": 0,
+        "fill": false,
+    })
+}
+
+fn config(
+    options: &ChartOptions,
+    default_title: &str,
+    datasets: Vec<Value>,
+    legend: bool,
+) -> Value {
+    let font = |size| json!({ "family": options.font_family, "size": size });
+    let title = options.title(default_title);
+    let ticks = |count| json!({ "maxTicksLimit": count, "font": font(options.label_font_size) });
+
+    json!({
+        "type": "line",
+        "data": { "datasets": datasets },
+        "options": {
+            "layout": {
+                "padding": {
+                    "top": options.margin_top,
+                    "right": options.margin_right,
+                    "bottom": options.margin_bottom,
+                    "left": options.margin_left,
+                },
+            },
+            "plugins": {
+                "title": {
+                    "display": title.is_some(),
+                    "text": title.unwrap_or_default(),
+                    "font": font(options.title_font_size),
+                },
+                "subtitle": {
+                    "display": options.subtitle.is_some(),
+                    "text": options.subtitle.as_deref().unwrap_or_default(),
+                    "font": font(options.label_font_size),
+                },
+                "legend": {
+                    "display": legend,
+                    "position": "top",
+                    "align": "start",
+                    "labels": { "font": font(options.label_font_size) },
+                },
+            },
+            "scales": {
+                "x": {
+                    "type": "time",
+                    "time": { "tooltipFormat": "yyyy-MM-dd" },
+                    "grid": { "display": false },
+                    "ticks": ticks(options.x_labels),
+                },
+                "y": {
+                    "beginAtZero": true,
+                    "title": { "display": true, "text": "Stars" },
+                    "ticks": ticks(options.y_labels),
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+
+    use crate::chart::SeriesKind;
+
+    #[test]
+    fn test_star_history_by_language() {
+        let vec = [
+            (date("2024-09-21"), "Rust".to_string(), 1),
+            (date("2024-09-21"), "Unknown language".to_string(), 2),
+            (date("2024-09-22"), "Rust".to_string(), 3),
+        ];
+        let series = SeriesKind::Language.series(&vec).unwrap();
+        let config = star_history_by_series(
+            &vec,
+            &series,
+            SeriesKind::Language.title(),
+            &ChartOptions::default(),
+        );
+
+        let datasets = config["data"]["datasets"].as_array().unwrap();
+        assert_eq!(datasets.len(), 2);
+        assert_eq!(datasets[0]["label"], "Rust");
+        assert_eq!(datasets[0]["borderColor"], "#dea584");
+        assert_eq!(
+            datasets[0]["data"],
+            json!([{ "x": "2024-09-21", "y": 1 }, { "x": "2024-09-22", "y": 3 }])
+        );
+        assert_eq!(datasets[1]["borderColor"], crate::chart::FALLBACK_COLOR);
+        assert_eq!(config["options"]["scales"]["x"]["type"], "time");
+
+        let config = star_history_by_series(
+            &vec,
+            &series,
+            SeriesKind::Language.title(),
+            &ChartOptions {
+                style: ChartStyle::Percent,
+                ..Default::default()
+            },
+        );
+        let datasets = config["data"]["datasets"].as_array().unwrap();
+        assert_eq!(
+            datasets[0]["data"],
+            json!([{ "x": "2024-09-21", "y": 33.33 }, { "x": "2024-09-22", "y": 60.0 }])
+        );
+        assert_eq!(datasets[1]["fill"], "-1");
+        assert_eq!(config["options"]["scales"]["y"]["stacked"], true);
+    }
+}
@@ -0,0 +1,307 @@
+//! Vega-Lite specifications of the charts.
+//!
+//! The specifications are self-contained, with the data inlined as rows of
+//! `date` (`YYYY-MM-DD`), `stars` and, for the language and repository charts,
+//! `language` or `repository` respectively.
+
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+
+use super::{
+    align_series, moving_average_label, rate_chart_title, ChartOptions, ChartStyle, Series,
+    SeriesKind, AVERAGE_COLOR, RATE_COLOR, RATE_LABEL, TOTAL_COLOR,
+};
+use crate::rate::StarRate;
+
+const SCHEMA: &str = "https://vega.github.io/schema/vega-lite/v5.json";
+
+pub fn star_history_by_series(
+    vec: &[(NaiveDate, String, i64)],
+    series: &[Series],
+    kind: SeriesKind,
+    options: &ChartOptions,
+) -> Value {
+    let names = series.iter().map(|series| &series.name).collect::<Vec<_>>();
+    let colors = series
+        .iter()
+        .map(|series| &series.color)
+        .collect::<Vec<_>>();
+
+    let row = |date: &NaiveDate, name: &str, stars: i64| {
+        json!({
+            "date": date.format("%Y-%m-%d").to_string(),
+            kind.field(): name,
+            "stars": stars,
+        })
+    };
+    // Stacking needs a value of every series on each date
+    let (values, mark) = match options.style {
+        ChartStyle::Lines => (
+            vec.iter()
+                .map(|(date, name, stars)| row(date, name, *stars))
+                .collect::<Vec<_>>(),
+            json!({ "type": "line", "strokeWidth": 1 }),
+        ),
+        ChartStyle::Stacked | ChartStyle::Percent => (
+            align_series(vec, series)
+                .iter()
+                .flat_map(|(date, values)| {
+                    names
+                        .iter()
+                        .zip(values.iter())
+                        .map(|(name, stars)| row(date, name, *stars))
+                })
+                .collect::<Vec<_>>(),
+            json!({ "type": "area" }),
+        ),
+    };
+
+    let mut spec = spec(
+        options,
+        kind.title(),
+        values,
+        mark,
+        Some(json!({
+            "field": kind.field(),
+            "type": "nominal",
+            "scale": { "domain": names, "range": colors },
+            "legend": { "title": null, "orient": "top-left" },
+        })),
+    );
+    let y = &mut spec["encoding"]["y"];
+    match options.style {
+        ChartStyle::Lines => {}
+        ChartStyle::Stacked => y["stack"] = json!("zero"),
+        ChartStyle::Percent => {
+            y["stack"] = json!("normalize");
+            y["title"] = json!("Share of stars");
+            y["axis"]["format"] = json!("%");
+        }
+    }
+    spec
+}
+
+pub fn total_star_history(
+    vec: &[(NaiveDate, i64)],
+    default_title: &str,
+    options: &ChartOptions,
+) -> Value {
+    let values = vec
+        .iter()
+        .map(|(date, stars)| {
+            json!({
+                "date": date.format("%Y-%m-%d").to_string(),
+                "stars": stars,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    spec(
+        options,
+        default_title,
+        values,
+        json!({ "type": "line", "strokeWidth": 1, "color": TOTAL_COLOR }),
+        None,
+    )
+}
+
+/// Bars of the new stars of each period, from `date` to `end`, with the
+/// moving average drawn as a line in another layer.
+pub fn star_rate(rate: &StarRate, options: &ChartOptions) -> Value {
+    let format = |date: NaiveDate| date.format("%Y-%m-%d").to_string();
+    let values = rate
+        .counts
+        .iter()
+        .map(|&(date, stars)| {
+            json!({
+                "date": format(date),
+                "end": format(rate.period.next(date)),
+                "stars": stars,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut spec = spec(
+        options,
+        &rate_chart_title(rate.period),
+        values,
+        json!({ "type": "bar", "color": RATE_COLOR }),
+        None,
+    );
+    spec["encoding"]["x2"] = json!({ "field": "end" });
+    spec["encoding"]["y"]["title"] = json!(RATE_LABEL);
+
+    let Some((window, average)) = &rate.average else {
+        return spec;
+    };
+
+    // The bars and the line have their own data, so they are split into layers
+    let label = moving_average_label(*window, rate.period);
+    let spec_object = spec.as_object_mut().unwrap();
+    let mut bars = json!({
+        "data": spec_object.remove("data").unwrap(),
+        "mark": spec_object.remove("mark").unwrap(),
+        "encoding": spec_object.remove("encoding").unwrap(),
+    });
+    bars["encoding"]["color"] = json!({
+        "datum": RATE_LABEL,
+        "scale": { "domain": [RATE_LABEL, label], "range": [RATE_COLOR, AVERAGE_COLOR] },
+        "legend": { "title": null, "orient": "top-left" },
+    });
+
+    let mut line = json!({
+        "data": {
+            "values": average
+                .iter()
+                .map(|&(date, value)| json!({ "date": format(date), "average": value }))
+                .collect::<Vec<_>>(),
+        },
+        "mark": { "type": "line", "strokeWidth": 2 },
+        "encoding": bars["encoding"].clone(),
+    });
+    let encoding = line["encoding"].as_object_mut().unwrap();
+    encoding.remove("x2");
+    encoding.insert("color".to_string(), json!({ "datum": label }));
+    line["encoding"]["y"]["field"] = json!("average");
+
+    spec["layer"] = json!([bars, line]);
+    spec
+}
+
+fn spec(
+    options: &ChartOptions,
+    default_title: &str,
+    values: Vec<Value>,
+    mark: Value,
+    color: Option<Value>,
+) -> Value {
+    let axis = |count| json!({ "tickCount": count, "labelFontSize": options.label_font_size });
+    let mut encoding = json!({
+        "x": {
+            "field": "date",
+            "type": "temporal",
+            "title": null,
+            "axis": axis(options.x_labels),
+        },
+        "y": {
+            "field": "stars",
+            "type": "quantitative",
+            "title": "Stars",
+            "axis": axis(options.y_labels),
+        },
+    });
+    if let Some(color) = color {
+        encoding["color"] = color;
+    }
+
+    let mut spec = json!({
+        "$schema": SCHEMA,
+        "width": options.width,
+        "height": options.height,
+        "padding": {
+            "top": options.margin_top,
+            "right": options.margin_right,
+            "bottom": options.margin_bottom,
+            "left": options.margin_left,
+        },
+        "config": { "font": options.font_family },
+        "data": { "values": values },
+        "mark": mark,
+        "encoding": encoding,
+    });
+    // The subtitle is shown even if the title is hidden, with an empty text
+    let title = options.title(default_title);
+    if title.is_some() || options.subtitle.is_some() {
+        spec["title"] = json!({
+            "text": title.unwrap_or_default(),
+            "fontSize": options.title_font_size,
+        });
+        if let Some(subtitle) = &options.subtitle {
+            spec["title"]["subtitle"] = json!(subtitle);
+            spec["title"]["subtitleFontSize"] = json!(options.label_font_size);
+        }
+    }
+    spec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+    use crate::rate::RatePeriod;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn test_star_rate() {
+        let daily = [(date("2024-09-21"), 1), (date("2024-10-08"), 4)];
+
+        let spec = star_rate(
+            &StarRate::new(&daily, RatePeriod::Month, None),
+            &ChartOptions::default(),
+        );
+        assert_eq!(spec["mark"]["type"], "bar");
+        assert_eq!(
+            spec["data"]["values"][0],
+            json!({ "date": "2024-09-01", "end": "2024-10-01", "stars": 1 })
+        );
+
+        let spec = star_rate(
+            &StarRate::new(&daily, RatePeriod::Month, NonZeroUsize::new(2)),
+            &ChartOptions::default(),
+        );
+        assert!(spec.get("mark").is_none());
+        let line = &spec["layer"][1];
+        assert_eq!(
+            line["data"]["values"],
+            json!([{ "date": "2024-10-01", "average": 2.5 }])
+        );
+        assert_eq!(line["encoding"]["y"]["field"], "average");
+        assert!(line["encoding"].get("x2").is_none());
+    }
+
+    #[test]
+    fn test_star_history_by_language() {
+        let vec = [
+            (date("2024-09-21"), "Rust".to_string(), 1),
+            (date("2024-09-22"), "Go".to_string(), 2),
+        ];
+        let series = SeriesKind::Language.series(&vec).unwrap();
+        let spec = star_history_by_series(
+            &vec,
+            &series,
+            SeriesKind::Language,
+            &ChartOptions::default(),
+        );
+
+        assert_eq!(spec["$schema"], SCHEMA);
+        assert_eq!(spec["title"]["text"], SeriesKind::Language.title());
+        assert!(spec["title"].get("subtitle").is_none());
+        assert_eq!(
+            spec["data"]["values"][1],
+            json!({ "date": "2024-09-22", "language": "Go", "stars": 2 })
+        );
+        let scale = &spec["encoding"]["color"]["scale"];
+        assert_eq!(scale["domain"], json!(["Rust", "Go"]));
+        assert_eq!(scale["range"], json!(["#dea584", "#00ADD8"]));
+
+        let spec = star_history_by_series(
+            &vec,
+            &series,
+            SeriesKind::Language,
+            &ChartOptions {
+                style: ChartStyle::Stacked,
+                title: Some(String::new()),
+                subtitle: Some("Subtitle".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(spec["mark"]["type"], "area");
+        assert_eq!(spec["title"]["subtitle"], "Subtitle");
+        assert_eq!(spec["title"]["text"], "");
+        assert_eq!(spec["encoding"]["y"]["stack"], "zero");
+        assert_eq!(
+            spec["data"]["values"][1],
+            json!({ "date": "2024-09-21", "language": "Go", "stars": 0 })
+        );
+    }
+}
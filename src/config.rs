@@ -0,0 +1,73 @@
+//! Config file of the application, written in TOML. All settings are optional:
+//!
+//! ```toml
+//! [chart]
+//! width = 800
+//! height = 300
+//! title = "Stars of my projects"
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::chart::ChartOptions;
+
+/// Environment variable that specifies the path to the config file.
+pub const CONFIG_FILE_ENV: &str = "YASTAR_CONFIG_FILE";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Default options of charts, which can be overridden by command line
+    /// options.
+    pub chart: ChartOptions,
+}
+
+/// Return the path to the config file, if configured.
+pub fn config_path() -> Option<PathBuf> {
+    std::env::var_os(CONFIG_FILE_ENV).map(PathBuf::from)
+}
+
+impl Config {
+    /// Read the config from a file. A missing file is treated as empty.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display()))?,
+        };
+        Self::parse(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+            [chart]
+            width = 800
+            title = ""
+            font_family = "Inter"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.chart.width, 800);
+        assert_eq!(config.chart.height, ChartOptions::default().height);
+        assert_eq!(config.chart.title.as_deref(), Some(""));
+        assert_eq!(config.chart.font_family, "Inter");
+
+        assert_eq!(Config::parse("").unwrap().chart.width, 500);
+        assert!(Config::parse("[chart]\nwidht = 800").is_err());
+    }
+}
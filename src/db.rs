@@ -1,60 +1,259 @@
-use chrono::NaiveDate;
-use duckdb::{params, Connection, DropBehavior};
-use std::collections::HashSet;
+use anyhow::{anyhow, Context};
+use chrono::{NaiveDate, NaiveDateTime};
+use duckdb::{params, Connection};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
 
-pub fn setup(conn: &Connection) {
+/// Migrations of the schema, applied in order. The schema version of a
+/// database is the number of migrations applied to it.
+///
+/// Never modify a migration once it is released, but append a new one.
+/// Databases created before versioning have no `schema_version` table, so the
+/// migrations up to that point are written to be idempotent.
+const MIGRATIONS: &[&str] = &[
+    // 1: Initial schema
     // The schema is pretty dumb because it is meant for analytic purposes.
-    let _ = conn.execute_batch(
-        "CREATE TABLE IF NOT EXISTS star_counts (
-           owner text NOT NULL,
-           name text NOT NULL,
-           stargazers int NOT NULL
-         );
-
-         TRUNCATE star_counts;
-
-         CREATE TABLE IF NOT EXISTS repository_primary_languages (
-           owner text NOT NULL,
-           name text NOT NULL,
-           primary_language text
-         );
-
-         TRUNCATE repository_primary_languages;
-
-         -- Persisted to save API usage.
-         CREATE TABLE IF NOT EXISTS original_statuses (
-           owner text NOT NULL,
-           name text NOT NULL,
-           original bool NOT NULL
-         );
-
-         -- Persisted to save API usage.
-         CREATE TABLE IF NOT EXISTS stargazers (
-           owner text NOT NULL,
-           name text NOT NULL,
-           starred_at timestamp NOT NULL,
-           starred_by text NOT NULL,
-         );
-
-         DROP VIEW IF EXISTS total_stars_by_language;
-         CREATE VIEW total_stars_by_language AS
-         SELECT
-           l.primary_language,
-           sum(s.stargazers) AS stargazers
-         FROM
-           repository_primary_languages l
-           INNER JOIN star_counts s ON l.owner = s.owner
-             AND l.name = s.name
-           INNER JOIN original_statuses o ON l.owner = o.owner
-             AND l.name = o.name
-         WHERE
-           o.original
-         GROUP BY
-           l.primary_language
-         ORDER BY
-           stargazers DESC;
-         ",
-    );
+    "CREATE TABLE IF NOT EXISTS star_counts (
+       owner text NOT NULL,
+       name text NOT NULL,
+       stargazers int NOT NULL
+     );
+
+     CREATE TABLE IF NOT EXISTS repository_primary_languages (
+       owner text NOT NULL,
+       name text NOT NULL,
+       primary_language text
+     );
+
+     -- Persisted to save API usage.
+     CREATE TABLE IF NOT EXISTS original_statuses (
+       owner text NOT NULL,
+       name text NOT NULL,
+       original bool NOT NULL
+     );
+
+     -- Persisted to save API usage.
+     CREATE TABLE IF NOT EXISTS stargazers (
+       owner text NOT NULL,
+       name text NOT NULL,
+       starred_at timestamp NOT NULL,
+       starred_by text NOT NULL,
+     );
+
+     CREATE OR REPLACE VIEW total_stars_by_language AS
+     SELECT
+       l.primary_language,
+       sum(s.stargazers) AS stargazers
+     FROM
+       repository_primary_languages l
+       INNER JOIN star_counts s ON l.owner = s.owner
+         AND l.name = s.name
+       INNER JOIN original_statuses o ON l.owner = o.owner
+         AND l.name = o.name
+     WHERE
+       o.original
+     GROUP BY
+       l.primary_language
+     ORDER BY
+       stargazers DESC;",
+    // 2: Progress of fetching stargazers of a repository, so an interrupted
+    // update can be resumed. A row is deleted once all stargazers of the
+    // repository are fetched.
+    "CREATE TABLE IF NOT EXISTS stargazer_checkpoints (
+       owner text NOT NULL,
+       name text NOT NULL,
+       cursor text NOT NULL,
+       total_count int NOT NULL,
+       fetched_at timestamp NOT NULL
+     );",
+    // 3: How each originality status was decided, so stale statuses can be
+    // re-checked. They are null in rows saved before this migration.
+    "ALTER TABLE original_statuses ADD COLUMN IF NOT EXISTS decided_at timestamp;
+     ALTER TABLE original_statuses ADD COLUMN IF NOT EXISTS method text;
+     ALTER TABLE original_statuses ADD COLUMN IF NOT EXISTS heuristic_version int;
+     ALTER TABLE original_statuses ADD COLUMN IF NOT EXISTS sample_size int;
+     ALTER TABLE original_statuses ADD COLUMN IF NOT EXISTS ratio double;",
+    // 4: Star counts reported by GitHub on every update. Unlike stargazers,
+    // they reflect unstars.
+    "CREATE TABLE star_count_snapshots (
+       owner text NOT NULL,
+       name text NOT NULL,
+       fetched_at timestamp NOT NULL,
+       stargazers int NOT NULL
+     );",
+    // 5: Unstars found by comparing the stored stargazers with the full list
+    "ALTER TABLE stargazers ADD COLUMN unstarred_detected_at timestamp;
+
+     CREATE TABLE stargazer_reconciliations (
+       owner text NOT NULL,
+       name text NOT NULL,
+       reconciled_at timestamp NOT NULL
+     );",
+    // 6: Identity of repositories by their node IDs, which persist across
+    // renames and transfers, and the owner/name pairs each one has had
+    "CREATE TABLE repositories (
+       id text PRIMARY KEY,
+       owner text NOT NULL,
+       name text NOT NULL
+     );
+
+     CREATE TABLE repository_aliases (
+       id text NOT NULL,
+       owner text NOT NULL,
+       name text NOT NULL,
+       first_seen_at timestamp NOT NULL
+     );",
+    // 7: At most one row per stargazer of a repository. New stargazers are
+    // appended to the staging table and then upserted, since appenders cannot
+    // handle conflicts.
+    "CREATE UNIQUE INDEX stargazers_repository_user ON stargazers (owner, name, starred_by);
+
+     CREATE TABLE stargazers_staging (
+       owner text NOT NULL,
+       name text NOT NULL,
+       starred_at timestamp NOT NULL,
+       starred_by text NOT NULL
+     );",
+];
+
+/// Version of the migration that adds the unique index on stargazers, which
+/// fails if the table contains duplicates.
+const UNIQUE_STARGAZERS_VERSION: usize = 7;
+
+/// Tables with rows keyed by `(owner, name)`, which are re-keyed when a
+/// repository is renamed or transferred. `star_counts` is not included, as it
+/// is filled from scratch on every update.
+const REPOSITORY_TABLES: &[&str] = &[
+    "repository_primary_languages",
+    "original_statuses",
+    "stargazers",
+    "stargazer_checkpoints",
+    "stargazer_reconciliations",
+    "star_count_snapshots",
+];
+
+/// Bring the schema of the database up to date. Each migration is applied in a
+/// transaction along with the version, so a failed migration leaves the
+/// database at the previous version.
+pub fn setup(conn: &mut Connection) -> anyhow::Result<()> {
+    migrate(conn, MIGRATIONS.len())
+}
+
+/// Apply the migrations up to the target version.
+fn migrate(conn: &mut Connection, target: usize) -> anyhow::Result<()> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version int NOT NULL);")?;
+
+    let version = schema_version(conn)?;
+    if version > MIGRATIONS.len() {
+        Err(anyhow!(
+            "The database has schema version {}, but this version of yastar only supports up to {}. Please upgrade yastar",
+            version,
+            MIGRATIONS.len()
+        ))?;
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().take(target).skip(version) {
+        let tx = conn.transaction()?;
+        if i + 1 == UNIQUE_STARGAZERS_VERSION {
+            let duplicates = count_duplicate_stargazers(&tx)?;
+            if duplicates > 0 {
+                Err(anyhow!(
+                    "The database contains {} duplicate stargazer rows. Run `yastar db dedupe` to remove them",
+                    duplicates
+                ))?;
+            }
+        }
+        tx.execute_batch(migration)
+            .with_context(|| format!("Failed to migrate the database to version {}", i + 1))?;
+        tx.execute("DELETE FROM schema_version", [])?;
+        tx.execute("INSERT INTO schema_version VALUES ($1)", params![i + 1])?;
+        tx.commit()?;
+        info!(version = i + 1, "migrated the database");
+    }
+
+    Ok(())
+}
+
+fn schema_version(conn: &Connection) -> anyhow::Result<usize> {
+    let version: Option<i64> =
+        conn.query_row("SELECT max(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })?;
+    Ok(version.unwrap_or(0) as usize)
+}
+
+fn count_duplicate_stargazers(conn: &Connection) -> anyhow::Result<usize> {
+    let count: i64 = conn.query_row(
+        "SELECT count(*) - count(DISTINCT (owner, name, starred_by)) FROM stargazers",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count as usize)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DuplicateStargazers {
+    pub owner: String,
+    pub name: String,
+    /// Number of rows removed.
+    pub count: usize,
+}
+
+/// Remove duplicate rows of the same stargazer of a repository, keeping the
+/// active row or the earliest one. This can be run on a database that cannot
+/// be migrated because of the duplicates. Return the number of rows removed
+/// from each repository.
+pub fn dedupe_stargazers(conn: &mut Connection) -> anyhow::Result<Vec<DuplicateStargazers>> {
+    migrate(conn, UNIQUE_STARGAZERS_VERSION - 1)?;
+
+    let tx = conn.transaction()?;
+
+    let duplicates = {
+        let mut stmt = tx.prepare(
+            "SELECT owner, name, count(*) - count(DISTINCT starred_by) AS duplicates
+             FROM stargazers
+             GROUP BY owner, name
+             HAVING duplicates > 0
+             ORDER BY owner, name",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut result = Vec::new();
+        while let Some(row) = rows.next()? {
+            result.push(DuplicateStargazers {
+                owner: row.get(0)?,
+                name: row.get(1)?,
+                count: row.get::<_, i64>(2)? as usize,
+            });
+        }
+        result
+    };
+
+    tx.execute_batch(
+        "DELETE FROM stargazers WHERE rowid IN (
+           SELECT rowid FROM (
+             SELECT rowid, row_number() OVER (
+               PARTITION BY owner, name, starred_by
+               ORDER BY unstarred_detected_at IS NOT NULL, starred_at
+             ) AS n
+             FROM stargazers
+           )
+           WHERE n > 1
+         );",
+    )?;
+
+    tx.commit()?;
+
+    Ok(duplicates)
+}
+
+/// Clear data on the starred repositories, which is fetched from scratch on
+/// every update.
+pub fn clear_repositories(conn: &Connection) -> anyhow::Result<()> {
+    conn.execute_batch(
+        "TRUNCATE star_counts;
+         TRUNCATE repository_primary_languages;",
+    )?;
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -77,8 +276,132 @@ pub fn insert_star_counts<'a>(
     Ok(())
 }
 
-pub fn update_star_count<'a>(
+#[derive(Debug)]
+pub struct RepositoryIdentity<'a> {
+    /// Node ID of the repository.
+    pub id: &'a str,
+    pub owner: &'a str,
+    pub name: &'a str,
+}
+
+/// A repository whose owner or name has changed since the last update.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RepositoryRename {
+    pub old_owner: String,
+    pub old_name: String,
+    pub new_owner: String,
+    pub new_name: String,
+}
+
+/// Record the identities of repositories, and move the rows of repositories
+/// that have been renamed or transferred to their new owner/name. Return the
+/// renames.
+pub fn update_repository_identities(
+    conn: &mut Connection,
+    repos: &[RepositoryIdentity],
+) -> anyhow::Result<Vec<RepositoryRename>> {
+    let known = {
+        let mut stmt = conn.prepare("SELECT id, owner, name FROM repositories")?;
+        let mut rows = stmt.query([])?;
+        let mut map: HashMap<String, (String, String)> = HashMap::new();
+        while let Some(row) = rows.next()? {
+            map.insert(row.get(0)?, (row.get(1)?, row.get(2)?));
+        }
+        map
+    };
+
+    let renames = repos
+        .iter()
+        .filter_map(|repo| match known.get(repo.id) {
+            Some((owner, name)) if owner != repo.owner || name != repo.name => Some((
+                repo.id,
+                RepositoryRename {
+                    old_owner: owner.clone(),
+                    old_name: name.clone(),
+                    new_owner: repo.owner.to_string(),
+                    new_name: repo.name.to_string(),
+                },
+            )),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // Rows are moved through a temporary key, so repositories that swap names
+    // are not mixed up. Node IDs never collide with owner names, which cannot
+    // contain underscores. DuckDB checks the unique index on stargazers before
+    // a transaction commits, so each step is committed separately. If the
+    // second step fails, the renames are detected again on the next update and
+    // the rows at the temporary keys are moved then.
+    let tx = conn.transaction()?;
+    for table in REPOSITORY_TABLES {
+        let sql =
+            format!("UPDATE {table} SET owner = $1, name = $2 WHERE owner = $3 AND name = $4");
+        for (id, rename) in renames.iter() {
+            tx.execute(&sql, params![id, "", rename.old_owner, rename.old_name])?;
+        }
+    }
+    tx.commit()?;
+
+    let tx = conn.transaction()?;
+    for table in REPOSITORY_TABLES {
+        let sql =
+            format!("UPDATE {table} SET owner = $1, name = $2 WHERE owner = $3 AND name = $4");
+        for (id, rename) in renames.iter() {
+            tx.execute(&sql, params![rename.new_owner, rename.new_name, id, ""])?;
+        }
+    }
+
+    let now = current_timestamp();
+    for repo in repos {
+        match known.get(repo.id) {
+            Some((owner, name)) if owner == repo.owner && name == repo.name => continue,
+            Some(_) => {
+                tx.execute(
+                    "UPDATE repositories SET owner = $2, name = $3 WHERE id = $1",
+                    params![repo.id, repo.owner, repo.name],
+                )?;
+            }
+            None => {
+                tx.execute(
+                    "INSERT INTO repositories VALUES ($1, $2, $3)",
+                    params![repo.id, repo.owner, repo.name],
+                )?;
+            }
+        }
+        tx.execute(
+            "INSERT INTO repository_aliases VALUES ($1, $2, $3, $4)",
+            params![repo.id, repo.owner, repo.name, now],
+        )?;
+    }
+
+    tx.commit()?;
+
+    Ok(renames.into_iter().map(|(_, rename)| rename).collect())
+}
+
+/// Append the star counts to the history, with the same timestamp for all of
+/// the repositories.
+pub fn insert_star_count_snapshots<'a>(
     conn: &mut Connection,
+    repos: &Vec<StarCountEntry<'a>>,
+) -> anyhow::Result<()> {
+    let fetched_at = current_timestamp();
+    let mut app = conn.appender("star_count_snapshots")?;
+
+    for repo in repos {
+        app.append_row(params![
+            repo.owner,
+            repo.name,
+            fetched_at,
+            repo.stargazer_count
+        ])?;
+    }
+
+    Ok(())
+}
+
+pub fn update_star_count(
+    conn: &Connection,
     owner: &str,
     name: &str,
     count: i64,
@@ -95,27 +418,167 @@ pub fn update_star_count<'a>(
     Ok(())
 }
 
-pub fn original_status_keys(conn: &Connection) -> anyhow::Result<HashSet<(String, String)>> {
-    let mut stmt = conn.prepare("SELECT owner, name FROM original_statuses")?;
+/// How the originality of a repository was decided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecisionMethod {
+    /// The repository is owned by the user.
+    Owner,
+    /// The first commits of the repository were checked.
+    Heuristic,
+    /// The originality was set manually.
+    Override,
+}
+
+impl DecisionMethod {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Heuristic => "heuristic",
+            Self::Override => "override",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "owner" => Some(Self::Owner),
+            "heuristic" => Some(Self::Heuristic),
+            "override" => Some(Self::Override),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OriginalStatus {
+    pub original: bool,
+    pub method: DecisionMethod,
+    /// Version of the heuristic, if decided by the heuristic.
+    pub heuristic_version: Option<i64>,
+    /// Number of commits checked by the heuristic.
+    pub sample_size: Option<i64>,
+    /// Ratio of the commits authored by the user.
+    pub ratio: Option<f64>,
+}
+
+impl OriginalStatus {
+    pub fn owned() -> Self {
+        Self {
+            original: true,
+            method: DecisionMethod::Owner,
+            heuristic_version: None,
+            sample_size: None,
+            ratio: None,
+        }
+    }
+
+    pub fn overridden(original: bool) -> Self {
+        Self {
+            original,
+            method: DecisionMethod::Override,
+            heuristic_version: None,
+            sample_size: None,
+            ratio: None,
+        }
+    }
+}
+
+/// Metadata of a saved originality status.
+#[derive(Debug)]
+pub struct OriginalStatusRecord {
+    pub owner: String,
+    pub name: String,
+    pub original: bool,
+    /// `None` if the status was saved by an older version.
+    pub method: Option<DecisionMethod>,
+    pub heuristic_version: Option<i64>,
+    pub decided_at: Option<NaiveDateTime>,
+}
+
+pub fn original_status_records(conn: &Connection) -> anyhow::Result<Vec<OriginalStatusRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT
+           owner,
+           name,
+           original,
+           method,
+           heuristic_version,
+           strftime(decided_at, '%Y-%m-%dT%H:%M:%S')
+         FROM original_statuses",
+    )?;
     let mut rows = stmt.query([])?;
 
-    let mut set = HashSet::new();
+    let mut vec = Vec::new();
     while let Some(row) = rows.next()? {
-        set.insert((row.get(0)?, row.get(1)?));
+        let method: Option<String> = row.get(3)?;
+        let decided_at: Option<String> = row.get(5)?;
+        vec.push(OriginalStatusRecord {
+            owner: row.get(0)?,
+            name: row.get(1)?,
+            original: row.get(2)?,
+            method: method.as_deref().and_then(DecisionMethod::parse),
+            heuristic_version: row.get(4)?,
+            decided_at: decided_at
+                .map(|s| NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M:%S"))
+                .transpose()?,
+        });
     }
 
-    Ok(set)
+    Ok(vec)
 }
 
 pub fn insert_original_status(
     conn: &Connection,
     owner: &str,
     name: &str,
-    is_original: bool,
+    status: &OriginalStatus,
+) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT INTO original_statuses
+           (owner, name, original, decided_at, method, heuristic_version, sample_size, ratio)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        params![
+            owner,
+            name,
+            status.original,
+            current_timestamp(),
+            status.method.as_str(),
+            status.heuristic_version,
+            status.sample_size,
+            status.ratio
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Replace the originality of a repository, e.g. on a re-check or from a
+/// manual override.
+pub fn set_original_status(
+    conn: &mut Connection,
+    owner: &str,
+    name: &str,
+    status: &OriginalStatus,
 ) -> anyhow::Result<()> {
-    let mut app = conn.appender("original_statuses")?;
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "DELETE FROM original_statuses WHERE owner = $1 AND name = $2",
+        params![owner, name],
+    )?;
+    insert_original_status(&tx, owner, name, status)?;
+
+    tx.commit()?;
+
+    Ok(())
+}
 
-    app.append_row(params![owner, name, is_original])?;
+/// Forget the originality of a repository, so it is checked again on the next
+/// update.
+pub fn delete_original_status(conn: &Connection, owner: &str, name: &str) -> anyhow::Result<()> {
+    conn.execute(
+        "DELETE FROM original_statuses WHERE owner = $1 AND name = $2",
+        params![owner, name],
+    )?;
 
     Ok(())
 }
@@ -147,6 +610,7 @@ pub fn get_newly_starred_original_repositories(
                 AND orig.original
               LEFT OUTER JOIN stargazers old ON new.owner = old.owner
               AND new.name = old.name
+              AND old.unstarred_detected_at IS NULL
             GROUP BY
               new.owner,
               new.name,
@@ -190,47 +654,310 @@ pub struct StargazerEntry {
     pub starred_at: String,
 }
 
+/// Insert stargazers of a repository. A stargazer that already exists is kept,
+/// unless it has unstarred the repository, in which case it is restored with
+/// the new timestamp. Pages that overlap or shift between requests are thus
+/// safe to insert.
+fn append_stargazers(
+    conn: &Connection,
+    owner: &str,
+    name: &str,
+    items: Vec<StargazerEntry>,
+) -> anyhow::Result<()> {
+    {
+        let mut app = conn.appender("stargazers_staging")?;
+        for item in items {
+            app.append_row(params![owner, name, item.starred_at, item.login])?;
+        }
+    }
+
+    conn.execute_batch(
+        "INSERT INTO stargazers
+         SELECT owner, name, min(starred_at), starred_by, NULL
+         FROM stargazers_staging
+         GROUP BY owner, name, starred_by
+         ON CONFLICT (owner, name, starred_by) DO UPDATE
+         SET starred_at = excluded.starred_at, unstarred_detected_at = NULL
+         WHERE stargazers.unstarred_detected_at IS NOT NULL;
+
+         DELETE FROM stargazers_staging;",
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
 pub fn insert_stargazers(
     conn: &mut Connection,
     owner: &str,
     name: &str,
     items: Vec<StargazerEntry>,
 ) -> anyhow::Result<()> {
-    // The records are inserted in descending order, so it's safer to use a
-    // transaction.
-    let mut tx = conn.transaction()?;
-    tx.set_drop_behavior(DropBehavior::Commit);
-    let mut app = tx.appender("stargazers")?;
+    save_stargazers_page(
+        conn,
+        owner,
+        name,
+        items,
+        &StargazersProgress::Complete { star_count: None },
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct StargazerCheckpoint {
+    /// Cursor for fetching the remaining stargazers.
+    pub cursor: String,
+    /// The number of stargazers expected after the fetch is complete.
+    pub total_count: i64,
+}
+
+pub fn get_stargazer_checkpoint(
+    conn: &Connection,
+    owner: &str,
+    name: &str,
+) -> anyhow::Result<Option<StargazerCheckpoint>> {
+    let mut stmt = conn.prepare(
+        "SELECT cursor, total_count FROM stargazer_checkpoints
+         WHERE owner = $1 AND name = $2
+         ORDER BY fetched_at DESC
+         LIMIT 1",
+    )?;
+    let mut rows = stmt.query(params![owner, name])?;
+
+    match rows.next()? {
+        Some(row) => Ok(Some(StargazerCheckpoint {
+            cursor: row.get(0)?,
+            total_count: row.get(1)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Progress of fetching stargazers of a repository.
+#[derive(Debug)]
+pub enum StargazersProgress {
+    /// There are more stargazers to fetch.
+    Partial(StargazerCheckpoint),
+    /// All stargazers have been fetched. If the star count has changed since
+    /// the repositories were fetched, it contains the new count.
+    Complete { star_count: Option<i64> },
+}
+
+/// Insert a page of stargazers and record the progress in a single
+/// transaction.
+///
+/// The records are inserted in descending order, so an interrupted fetch must
+/// be resumed from the checkpoint rather than from the latest stargazer.
+pub fn save_stargazers_page(
+    conn: &mut Connection,
+    owner: &str,
+    name: &str,
+    items: Vec<StargazerEntry>,
+    progress: &StargazersProgress,
+) -> anyhow::Result<()> {
+    let tx = conn.transaction()?;
+
+    append_stargazers(&tx, owner, name, items)?;
+
+    tx.execute(
+        "DELETE FROM stargazer_checkpoints WHERE owner = $1 AND name = $2",
+        params![owner, name],
+    )?;
 
-    for item in items {
-        let login = item.login;
-        app.append_row(params![owner, name, item.starred_at, login])?;
+    match progress {
+        StargazersProgress::Partial(checkpoint) => {
+            tx.execute(
+                "INSERT INTO stargazer_checkpoints VALUES ($1, $2, $3, $4, $5)",
+                params![
+                    owner,
+                    name,
+                    checkpoint.cursor,
+                    checkpoint.total_count,
+                    current_timestamp()
+                ],
+            )?;
+        }
+        StargazersProgress::Complete {
+            star_count: Some(count),
+        } => {
+            update_star_count(&tx, owner, name, *count)?;
+        }
+        StargazersProgress::Complete { star_count: None } => {}
     }
 
+    tx.commit()?;
+
     Ok(())
 }
 
+fn current_timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Return original repositories whose stargazers should be compared with the
+/// full list, i.e. those with fewer stars than the stored stargazers, and
+/// those not reconciled since `due_before` if given.
+pub fn get_repositories_to_reconcile(
+    conn: &Connection,
+    due_before: Option<NaiveDateTime>,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "WITH active AS (
+           SELECT
+             owner,
+             name,
+             count(*) AS count
+           FROM
+             stargazers
+           WHERE
+             unstarred_detected_at IS NULL
+           GROUP BY
+             owner,
+             name
+         ),
+         reconciliations AS (
+           SELECT
+             owner,
+             name,
+             max(reconciled_at) AS reconciled_at
+           FROM
+             stargazer_reconciliations
+           GROUP BY
+             owner,
+             name
+         )
+         SELECT
+           c.owner,
+           c.name
+         FROM
+           star_counts c
+           INNER JOIN original_statuses o ON c.owner = o.owner
+             AND c.name = o.name
+             AND o.original
+           INNER JOIN active a ON c.owner = a.owner
+             AND c.name = a.name
+           LEFT OUTER JOIN reconciliations r ON c.owner = r.owner
+             AND c.name = r.name
+         WHERE
+           c.stargazers < a.count
+           OR ($1 IS NOT NULL
+             AND (r.reconciled_at IS NULL
+               OR r.reconciled_at < CAST($1 AS timestamp)))
+         ORDER BY
+           c.owner,
+           c.name",
+    )?;
+    let due_before = due_before.map(|t| t.format("%Y-%m-%dT%H:%M:%S").to_string());
+    let mut rows = stmt.query(params![due_before])?;
+
+    let mut vec = Vec::new();
+    while let Some(row) = rows.next()? {
+        vec.push((row.get(0)?, row.get(1)?));
+    }
+
+    Ok(vec)
+}
+
+/// Compare the stored stargazers of a repository with the full list. Those
+/// missing from the list are marked as unstarred, and those missing from the
+/// database are added. Return the numbers of the unstarred and the added.
+pub fn reconcile_stargazers(
+    conn: &mut Connection,
+    owner: &str,
+    name: &str,
+    current: Vec<StargazerEntry>,
+) -> anyhow::Result<(usize, usize)> {
+    let tx = conn.transaction()?;
+
+    let active = {
+        let mut stmt = tx.prepare(
+            "SELECT DISTINCT starred_by FROM stargazers
+             WHERE owner = $1 AND name = $2 AND unstarred_detected_at IS NULL",
+        )?;
+        let mut rows = stmt.query(params![owner, name])?;
+        let mut set = HashSet::new();
+        while let Some(row) = rows.next()? {
+            set.insert(row.get::<_, String>(0)?);
+        }
+        set
+    };
+
+    let current_logins = current
+        .iter()
+        .map(|entry| entry.login.as_str())
+        .collect::<HashSet<_>>();
+
+    let now = current_timestamp();
+    let mut unstarred = 0;
+    for login in active.iter() {
+        if !current_logins.contains(login.as_str()) {
+            tx.execute(
+                "UPDATE stargazers SET unstarred_detected_at = $4
+                 WHERE owner = $1 AND name = $2 AND starred_by = $3
+                   AND unstarred_detected_at IS NULL",
+                params![owner, name, login, now],
+            )?;
+            unstarred += 1;
+        }
+    }
+
+    let added = current
+        .into_iter()
+        .filter(|entry| !active.contains(&entry.login))
+        .collect::<Vec<_>>();
+    let added_count = added.len();
+    append_stargazers(&tx, owner, name, added)?;
+
+    // The full list supersedes an incomplete fetch
+    tx.execute(
+        "DELETE FROM stargazer_checkpoints WHERE owner = $1 AND name = $2",
+        params![owner, name],
+    )?;
+    tx.execute(
+        "INSERT INTO stargazer_reconciliations VALUES ($1, $2, $3)",
+        params![owner, name, now],
+    )?;
+
+    tx.commit()?;
+
+    Ok((unstarred, added_count))
+}
+
+/// Events that change the number of stargazers, i.e. stars and, if
+/// `subtract_unstars` (`$1`) is true, detected unstars.
+const STAR_EVENTS_QUERY: &str = "SELECT
+       strftime(starred_at, '%Y-%m-%d') AS date,
+       owner,
+       name,
+       1 AS delta
+     FROM
+       stargazers
+     UNION ALL
+     SELECT
+       strftime(unstarred_detected_at, '%Y-%m-%d') AS date,
+       owner,
+       name,
+       -1 AS delta
+     FROM
+       stargazers
+     WHERE
+       $1
+       AND unstarred_detected_at IS NOT NULL";
+
 pub fn collect_star_history_by_language(
     conn: &mut Connection,
     min_stargazer_count: i64,
+    subtract_unstars: bool,
 ) -> anyhow::Result<Vec<(NaiveDate, String, i64)>> {
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         "WITH activities AS (
               SELECT
                 l.primary_language AS
                 LANGUAGE,
                 s.date,
-                count(s.idx) AS count
+                sum(s.delta) AS count
               FROM
                 repository_primary_languages l
-                INNER JOIN (
-                SELECT
-                  row_number() OVER () AS idx,
-                  strftime(starred_at, '%Y-%m-%d') AS date,
-                  OWNER,
-                  name
-                FROM
-                  stargazers) s ON l.owner = s.owner
+                INNER JOIN ({STAR_EVENTS_QUERY}) s ON l.owner = s.owner
                   AND l.name = s.name
               WHERE
                 l.primary_language IS NOT NULL
@@ -240,7 +967,7 @@ pub fn collect_star_history_by_language(
                   FROM
                     total_stars_by_language
                   WHERE
-                    stargazers >= $1)
+                    stargazers >= $2)
                 GROUP BY
                   l.primary_language,
                   s.date
@@ -254,10 +981,10 @@ pub fn collect_star_history_by_language(
               activities
             ORDER BY
               date
-            ",
-    )?;
+            "
+    ))?;
 
-    let mut rows = stmt.query(params![min_stargazer_count])?;
+    let mut rows = stmt.query(params![subtract_unstars, min_stargazer_count])?;
 
     let mut vec = Vec::new();
 
@@ -272,26 +999,31 @@ pub fn collect_star_history_by_language(
     Ok(vec)
 }
 
-pub fn collect_total_star_history(conn: &mut Connection) -> anyhow::Result<Vec<(NaiveDate, i64)>> {
-    let mut stmt = conn.prepare(
+pub fn collect_total_star_history(
+    conn: &mut Connection,
+    subtract_unstars: bool,
+) -> anyhow::Result<Vec<(NaiveDate, i64)>> {
+    let mut stmt = conn.prepare(&format!(
         "WITH cte AS (
               SELECT
-                row_number() OVER () AS idx,
-                strftime (starred_at, '%Y-%m-%d') AS date
+                date,
+                sum(delta) AS delta
               FROM
-                stargazers
+                ({STAR_EVENTS_QUERY})
+              GROUP BY
+                date
             )
             SELECT
               date,
-              count(idx) OVER (ORDER BY date ROWS UNBOUNDED PRECEDING) accum
+              sum(delta) OVER (ORDER BY date ROWS UNBOUNDED PRECEDING) accum
               FROM
                 cte
               ORDER BY
                 date
-            ",
-    )?;
+            "
+    ))?;
 
-    let mut rows = stmt.query([])?;
+    let mut rows = stmt.query(params![subtract_unstars])?;
 
     let mut vec = Vec::new();
 
@@ -305,35 +1037,504 @@ pub fn collect_total_star_history(conn: &mut Connection) -> anyhow::Result<Vec<(
     Ok(vec)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Collect the number of new stars per day, including the stars that have
+/// been removed since.
+pub fn collect_daily_new_stars(conn: &mut Connection) -> anyhow::Result<Vec<(NaiveDate, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT
+           strftime(starred_at, '%Y-%m-%d') AS date,
+           count(*)
+         FROM
+           stargazers
+         GROUP BY
+           date
+         ORDER BY
+           date",
+    )?;
 
-    fn setup_test_db() -> Connection {
-        let conn = Connection::open_in_memory().unwrap();
-        setup(&conn);
-        conn
-    }
+    let mut rows = stmt.query([])?;
 
-    #[test]
-    fn test_insert_star_counts() {
-        let mut conn = setup_test_db();
-        let test_repos = vec![StarCountEntry {
-            owner: "test_owner",
-            name: "test_repo",
-            stargazer_count: 42,
-        }];
+    let mut vec = Vec::new();
 
-        assert!(insert_star_counts(&mut conn, &test_repos).is_ok());
-        assert!(update_star_count(&mut conn, "test_owner", "test_repo", 43).is_ok());
+    while let Some(row) = rows.next()? {
+        let date_str: String = row.get(0)?;
+        let date = NaiveDate::parse_from_str(date_str.as_str(), "%Y-%m-%d")?;
+        vec.push((date, row.get(1)?));
     }
 
-    #[test]
-    fn test_insert_stargazers() {
-        let mut conn = setup_test_db();
+    Ok(vec)
+}
 
-        assert!(insert_stargazers(
-            &mut conn,
+/// Repositories to include in [`collect_star_history_by_repository`].
+#[derive(Debug, Clone)]
+pub enum RepositorySelection {
+    /// Repositories with the most stars at the end of the history.
+    Top(usize),
+    /// Repositories given as `(owner, name)`, compared case-insensitively.
+    Only(Vec<(String, String)>),
+}
+
+/// Collect the cumulative number of stargazers of each selected repository.
+/// Each entry is identified by `owner/name`.
+pub fn collect_star_history_by_repository(
+    conn: &mut Connection,
+    selection: &RepositorySelection,
+    subtract_unstars: bool,
+) -> anyhow::Result<Vec<(NaiveDate, String, i64)>> {
+    let mut stmt = conn.prepare(&format!(
+        "WITH activities AS (
+              SELECT
+                owner,
+                name,
+                date,
+                sum(delta) AS count
+              FROM
+                ({STAR_EVENTS_QUERY})
+              GROUP BY
+                owner,
+                name,
+                date
+            )
+            SELECT
+              date,
+              owner || '/' || name AS repository,
+              sum(count) OVER (PARTITION BY owner, name
+                ORDER BY date ROWS UNBOUNDED PRECEDING) accum
+            FROM
+              activities
+            ORDER BY
+              date,
+              repository
+            "
+    ))?;
+
+    let mut rows = stmt.query(params![subtract_unstars])?;
+
+    let mut vec = Vec::new();
+
+    while let Some(row) = rows.next()? {
+        let date_str: String = row.get(0)?;
+        let date = NaiveDate::parse_from_str(date_str.as_str(), "%Y-%m-%d")?;
+        vec.push((date, row.get(1)?, row.get(2)?));
+    }
+
+    select_repositories(vec, selection)
+}
+
+fn select_repositories(
+    by_repository: Vec<(NaiveDate, String, i64)>,
+    selection: &RepositorySelection,
+) -> anyhow::Result<Vec<(NaiveDate, String, i64)>> {
+    let selected: HashSet<String> = match selection {
+        RepositorySelection::Top(n) => {
+            // The entries are ordered by date, so the last one of each
+            // repository is its final count.
+            let mut latest = HashMap::new();
+            for (_, repository, count) in by_repository.iter() {
+                latest.insert(repository.as_str(), *count);
+            }
+            let mut ranking = latest.into_iter().collect::<Vec<_>>();
+            ranking.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+            ranking
+                .into_iter()
+                .take(*n)
+                .map(|(repository, _)| repository.to_lowercase())
+                .collect()
+        }
+        RepositorySelection::Only(repositories) => {
+            let known = by_repository
+                .iter()
+                .map(|(_, repository, _)| repository.to_lowercase())
+                .collect::<HashSet<_>>();
+            let unknown = repositories
+                .iter()
+                .map(|(owner, name)| format!("{owner}/{name}"))
+                .filter(|repository| !known.contains(&repository.to_lowercase()))
+                .collect::<Vec<_>>();
+            if !unknown.is_empty() {
+                return Err(anyhow!(
+                    "No stargazers of the repositories: {}",
+                    unknown.join(", ")
+                ));
+            }
+            repositories
+                .iter()
+                .map(|(owner, name)| format!("{owner}/{name}").to_lowercase())
+                .collect()
+        }
+    };
+
+    Ok(by_repository
+        .into_iter()
+        .filter(|(_, repository, _)| selected.contains(&repository.to_lowercase()))
+        .collect())
+}
+
+/// Collect the daily star counts of original repositories from the snapshots.
+/// The last snapshot of each day is used. Each entry is identified by
+/// `owner/name`.
+pub fn collect_net_star_history_by_repository(
+    conn: &mut Connection,
+) -> anyhow::Result<Vec<(NaiveDate, String, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT
+           strftime(s.fetched_at, '%Y-%m-%d') AS date,
+           s.owner || '/' || s.name AS repository,
+           arg_max(s.stargazers, s.fetched_at) AS stargazers
+         FROM
+           star_count_snapshots s
+           INNER JOIN original_statuses o ON s.owner = o.owner
+             AND s.name = o.name
+         WHERE
+           o.original
+         GROUP BY
+           date,
+           repository
+         ORDER BY
+           date,
+           repository",
+    )?;
+
+    let mut rows = stmt.query([])?;
+
+    let mut vec = Vec::new();
+
+    while let Some(row) = rows.next()? {
+        let date_str: String = row.get(0)?;
+        let date = NaiveDate::parse_from_str(date_str.as_str(), "%Y-%m-%d")?;
+        vec.push((date, row.get(1)?, row.get(2)?));
+    }
+
+    Ok(vec)
+}
+
+/// Collect the daily total of the star counts of original repositories from
+/// the snapshots. Unlike [`collect_total_star_history`], it decreases on
+/// unstars.
+pub fn collect_net_star_history(conn: &mut Connection) -> anyhow::Result<Vec<(NaiveDate, i64)>> {
+    let by_repository = collect_net_star_history_by_repository(conn)?;
+    Ok(sum_net_star_history(by_repository))
+}
+
+/// Sum the star counts per day. Every update snapshots all of the repositories,
+/// so a repository missing on a day, e.g. deleted or no longer original, is
+/// not counted on that day.
+fn sum_net_star_history(by_repository: Vec<(NaiveDate, String, i64)>) -> Vec<(NaiveDate, i64)> {
+    let mut vec: Vec<(NaiveDate, i64)> = Vec::new();
+
+    for (date, _, count) in by_repository {
+        match vec.last_mut() {
+            Some((last_date, total)) if *last_date == date => *total += count,
+            _ => vec.push((date, count)),
+        }
+    }
+
+    vec
+}
+
+/// Return the star counts of original repositories, the most starred first.
+pub fn get_original_star_counts(conn: &Connection) -> anyhow::Result<Vec<(String, String, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT
+           s.owner,
+           s.name,
+           s.stargazers
+         FROM
+           star_counts s
+           INNER JOIN original_statuses o ON s.owner = o.owner
+             AND s.name = o.name
+         WHERE
+           o.original
+         ORDER BY
+           s.stargazers DESC,
+           s.owner,
+           s.name",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut vec = Vec::new();
+    while let Some(row) = rows.next()? {
+        vec.push((row.get(0)?, row.get(1)?, row.get(2)?));
+    }
+
+    Ok(vec)
+}
+
+/// Return the stars of original repositories per primary language, which is
+/// `None` for repositories without one.
+pub fn get_total_stars_by_language(
+    conn: &Connection,
+) -> anyhow::Result<Vec<(Option<String>, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT primary_language, stargazers FROM total_stars_by_language
+         ORDER BY stargazers DESC, primary_language",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut vec = Vec::new();
+    while let Some(row) = rows.next()? {
+        vec.push((row.get(0)?, row.get(1)?));
+    }
+
+    Ok(vec)
+}
+
+/// Count the stored stargazers starred at or after `since` who have not
+/// unstarred the repository.
+pub fn count_stars_since(conn: &Connection, since: NaiveDateTime) -> anyhow::Result<i64> {
+    let count = conn.query_row(
+        "SELECT count(*) FROM stargazers
+         WHERE starred_at >= CAST($1 AS timestamp) AND unstarred_detected_at IS NULL",
+        params![since.format("%Y-%m-%dT%H:%M:%S").to_string()],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+
+    fn setup_test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        setup(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_net_star_history() {
+        let mut conn = setup_test_db();
+
+        let snapshots = [
+            ("repo1", "2024-09-21T11:00:00Z", 10),
+            ("repo1", "2024-09-21T18:00:00Z", 12),
+            ("repo2", "2024-09-21T11:00:00Z", 5),
+            // repo2 is missing, e.g. deleted
+            ("repo1", "2024-09-23T11:00:00Z", 11),
+            // Not original
+            ("repo3", "2024-09-23T11:00:00Z", 100),
+        ];
+        for (name, fetched_at, count) in snapshots {
+            conn.execute(
+                "INSERT INTO star_count_snapshots VALUES ('test_owner', $1, $2, $3)",
+                params![name, fetched_at, count],
+            )
+            .unwrap();
+        }
+        for name in ["repo1", "repo2"] {
+            insert_original_status(&conn, "test_owner", name, &OriginalStatus::owned()).unwrap();
+        }
+
+        let by_repository = collect_net_star_history_by_repository(&mut conn).unwrap();
+        assert_eq!(
+            by_repository
+                .iter()
+                .map(|(_, repository, count)| (repository.as_str(), *count))
+                .collect::<Vec<_>>(),
+            vec![
+                ("test_owner/repo1", 12),
+                ("test_owner/repo2", 5),
+                ("test_owner/repo1", 11)
+            ]
+        );
+
+        assert_eq!(
+            collect_net_star_history(&mut conn).unwrap(),
+            vec![(date("2024-09-21"), 17), (date("2024-09-23"), 11)]
+        );
+    }
+
+    #[test]
+    fn test_reconcile_stargazers() {
+        let mut conn = setup_test_db();
+
+        let stargazer = |login: &str, starred_at: &str| StargazerEntry {
+            login: login.to_string(),
+            starred_at: starred_at.to_string(),
+        };
+
+        insert_star_counts(
+            &mut conn,
+            &vec![StarCountEntry {
+                owner: "test_owner",
+                name: "test_repo",
+                stargazer_count: 2,
+            }],
+        )
+        .unwrap();
+        insert_original_status(&conn, "test_owner", "test_repo", &OriginalStatus::owned()).unwrap();
+        insert_stargazers(
+            &mut conn,
+            "test_owner",
+            "test_repo",
+            vec![
+                stargazer("user1", "2024-09-21T11:08:01Z"),
+                stargazer("user2", "2024-09-22T11:08:01Z"),
+                stargazer("user3", "2024-09-23T11:08:01Z"),
+            ],
+        )
+        .unwrap();
+
+        // The star count has dropped below the stored stargazers
+        assert_eq!(
+            get_repositories_to_reconcile(&conn, None).unwrap(),
+            vec![("test_owner".to_string(), "test_repo".to_string())]
+        );
+
+        let (unstarred, added) = reconcile_stargazers(
+            &mut conn,
+            "test_owner",
+            "test_repo",
+            vec![
+                stargazer("user1", "2024-09-21T11:08:01Z"),
+                stargazer("user4", "2024-09-24T11:08:01Z"),
+            ],
+        )
+        .unwrap();
+        assert_eq!((unstarred, added), (2, 1));
+
+        assert!(get_repositories_to_reconcile(&conn, None)
+            .unwrap()
+            .is_empty());
+        assert!(get_newly_starred_original_repositories(&conn)
+            .unwrap()
+            .is_empty());
+
+        let total = |conn: &mut Connection, subtract_unstars| {
+            collect_total_star_history(conn, subtract_unstars)
+                .unwrap()
+                .last()
+                .unwrap()
+                .1
+        };
+        assert_eq!(total(&mut conn, false), 4);
+        assert_eq!(total(&mut conn, true), 2);
+    }
+
+    #[test]
+    fn test_update_repository_identities() {
+        let mut conn = setup_test_db();
+
+        let identities = |names: &[(&'static str, &'static str, &'static str)]| {
+            names
+                .iter()
+                .map(|(id, owner, name)| RepositoryIdentity { id, owner, name })
+                .collect::<Vec<_>>()
+        };
+
+        let renames = update_repository_identities(
+            &mut conn,
+            &identities(&[
+                ("R_1", "test_owner", "repo1"),
+                ("R_2", "test_owner", "repo2"),
+            ]),
+        )
+        .unwrap();
+        assert!(renames.is_empty());
+
+        insert_stargazers(
+            &mut conn,
+            "test_owner",
+            "repo1",
+            vec![StargazerEntry {
+                login: "user1".to_string(),
+                starred_at: "2024-09-21T11:08:01Z".to_string(),
+            }],
+        )
+        .unwrap();
+        insert_original_status(&conn, "test_owner", "repo2", &OriginalStatus::owned()).unwrap();
+
+        // repo1 is transferred to an organization, and the two repositories
+        // swap their names
+        let renames = update_repository_identities(
+            &mut conn,
+            &identities(&[("R_1", "test_org", "repo2"), ("R_2", "test_owner", "repo1")]),
+        )
+        .unwrap();
+        assert_eq!(renames.len(), 2);
+        assert_eq!(renames[0].old_name, "repo1");
+        assert_eq!(renames[0].new_owner, "test_org");
+
+        let stargazers: Vec<(String, String)> = conn
+            .prepare("SELECT owner, name FROM stargazers")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            stargazers,
+            vec![("test_org".to_string(), "repo2".to_string())]
+        );
+
+        let records = original_status_records(&conn).unwrap();
+        assert_eq!(
+            (records[0].owner.as_str(), records[0].name.as_str()),
+            ("test_owner", "repo1")
+        );
+
+        let alias_count: i64 = conn
+            .query_row("SELECT count(*) FROM repository_aliases", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(alias_count, 4);
+    }
+
+    #[test]
+    fn test_migrations() {
+        // A database created before versioning
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE original_statuses (
+               owner text NOT NULL,
+               name text NOT NULL,
+               original bool NOT NULL
+             );
+             INSERT INTO original_statuses VALUES ('test_owner', 'test_repo', true);",
+        )
+        .unwrap();
+
+        setup(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+        let records = original_status_records(&conn).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].original);
+        assert_eq!(records[0].method, None);
+
+        // Idempotent
+        setup(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+
+        // A database from a newer version
+        conn.execute(
+            "UPDATE schema_version SET version = $1",
+            params![MIGRATIONS.len() + 1],
+        )
+        .unwrap();
+        assert!(setup(&mut conn).is_err());
+    }
+
+    #[test]
+    fn test_insert_star_counts() {
+        let mut conn = setup_test_db();
+        let test_repos = vec![StarCountEntry {
+            owner: "test_owner",
+            name: "test_repo",
+            stargazer_count: 42,
+        }];
+
+        assert!(insert_star_counts(&mut conn, &test_repos).is_ok());
+        assert!(update_star_count(&mut conn, "test_owner", "test_repo", 43).is_ok());
+    }
+
+    #[test]
+    fn test_insert_stargazers() {
+        let mut conn = setup_test_db();
+
+        assert!(insert_stargazers(
+            &mut conn,
             "test_owner",
             "test_repo",
             vec![StargazerEntry {
@@ -344,6 +1545,141 @@ mod tests {
         .is_ok());
     }
 
+    #[test]
+    fn test_dedupe_stargazers() {
+        let mut conn = setup_test_db();
+
+        let stargazer = |login: &str, starred_at: &str| StargazerEntry {
+            login: login.to_string(),
+            starred_at: starred_at.to_string(),
+        };
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT count(*) FROM stargazers", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        // Overlapping pages are upserted
+        insert_stargazers(
+            &mut conn,
+            "test_owner",
+            "test_repo",
+            vec![
+                stargazer("user1", "2024-09-21T11:08:01Z"),
+                stargazer("user2", "2024-09-22T11:08:01Z"),
+            ],
+        )
+        .unwrap();
+        insert_stargazers(
+            &mut conn,
+            "test_owner",
+            "test_repo",
+            vec![
+                stargazer("user2", "2024-09-22T11:08:01Z"),
+                stargazer("user3", "2024-09-23T11:08:01Z"),
+                stargazer("user3", "2024-09-23T11:08:01Z"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(count(&conn), 3);
+
+        // A database with duplicates from before the unique index
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, UNIQUE_STARGAZERS_VERSION - 1).unwrap();
+        conn.execute_batch(
+            "INSERT INTO stargazers VALUES
+               ('test_owner', 'test_repo', '2024-09-21T11:08:01Z', 'user1', '2024-10-01T00:00:00Z'),
+               ('test_owner', 'test_repo', '2024-09-25T11:08:01Z', 'user1', NULL),
+               ('test_owner', 'test_repo', '2024-09-22T11:08:01Z', 'user2', NULL),
+               ('test_owner', 'test_repo', '2024-09-22T11:08:01Z', 'user2', NULL);",
+        )
+        .unwrap();
+        assert!(setup(&mut conn).is_err());
+
+        assert_eq!(
+            dedupe_stargazers(&mut conn).unwrap(),
+            vec![DuplicateStargazers {
+                owner: "test_owner".to_string(),
+                name: "test_repo".to_string(),
+                count: 2,
+            }]
+        );
+        setup(&mut conn).unwrap();
+        assert_eq!(count(&conn), 2);
+        // The active row is kept
+        let unstarred: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM stargazers WHERE unstarred_detected_at IS NOT NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(unstarred, 0);
+    }
+
+    #[test]
+    fn test_stats_queries() {
+        let mut conn = setup_test_db();
+
+        insert_star_counts(
+            &mut conn,
+            &vec![
+                StarCountEntry {
+                    owner: "test_owner",
+                    name: "repo1",
+                    stargazer_count: 3,
+                },
+                StarCountEntry {
+                    owner: "test_owner",
+                    name: "repo2",
+                    stargazer_count: 7,
+                },
+                StarCountEntry {
+                    owner: "other_owner",
+                    name: "repo3",
+                    stargazer_count: 100,
+                },
+            ],
+        )
+        .unwrap();
+        insert_original_status(&conn, "test_owner", "repo1", &OriginalStatus::owned()).unwrap();
+        insert_original_status(&conn, "test_owner", "repo2", &OriginalStatus::owned()).unwrap();
+        insert_original_status(
+            &conn,
+            "other_owner",
+            "repo3",
+            &OriginalStatus::overridden(false),
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_original_star_counts(&conn).unwrap(),
+            vec![
+                ("test_owner".to_string(), "repo2".to_string(), 7),
+                ("test_owner".to_string(), "repo1".to_string(), 3),
+            ]
+        );
+
+        insert_stargazers(
+            &mut conn,
+            "test_owner",
+            "repo1",
+            vec![
+                StargazerEntry {
+                    login: "user1".to_string(),
+                    starred_at: "2024-09-01T00:00:00Z".to_string(),
+                },
+                StargazerEntry {
+                    login: "user2".to_string(),
+                    starred_at: "2024-09-21T11:08:01Z".to_string(),
+                },
+            ],
+        )
+        .unwrap();
+        let since =
+            NaiveDateTime::parse_from_str("2024-09-14T00:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        assert_eq!(count_stars_since(&conn, since).unwrap(), 1);
+    }
+
     #[test]
     fn test_insert_repository_primary_language() {
         let mut conn = setup_test_db();
@@ -361,11 +1697,54 @@ mod tests {
         let conn = setup_test_db();
 
         // Test inserting original status
-        assert!(insert_original_status(&conn, "test_owner", "test_repo", true).is_ok());
+        assert!(insert_original_status(
+            &conn,
+            "test_owner",
+            "test_repo",
+            &OriginalStatus {
+                original: false,
+                method: DecisionMethod::Heuristic,
+                heuristic_version: Some(2),
+                sample_size: Some(5),
+                ratio: Some(0.4),
+            }
+        )
+        .is_ok());
+
+        // Test retrieving original status records
+        let records = original_status_records(&conn).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].owner, "test_owner");
+        assert_eq!(records[0].name, "test_repo");
+        assert_eq!(records[0].method, Some(DecisionMethod::Heuristic));
+        assert_eq!(records[0].heuristic_version, Some(2));
+        assert!(records[0].decided_at.is_some());
+    }
+
+    #[test]
+    fn test_set_original_status() {
+        let mut conn = setup_test_db();
+
+        insert_original_status(&conn, "test_owner", "test_repo", &OriginalStatus::owned()).unwrap();
+        set_original_status(
+            &mut conn,
+            "test_owner",
+            "test_repo",
+            &OriginalStatus::overridden(false),
+        )
+        .unwrap();
+
+        let original: Vec<bool> = conn
+            .prepare("SELECT original FROM original_statuses WHERE name = 'test_repo'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(original, vec![false]);
 
-        // Test retrieving original status keys
-        let keys = original_status_keys(&conn).unwrap();
-        assert!(keys.contains(&("test_owner".to_string(), "test_repo".to_string())));
+        delete_original_status(&conn, "test_owner", "test_repo").unwrap();
+        assert!(original_status_records(&conn).unwrap().is_empty());
     }
 
     #[test]
@@ -407,7 +1786,7 @@ mod tests {
         .unwrap();
 
         for (name, language, _, _) in data.iter() {
-            insert_original_status(&conn, "test_owner", name, true).unwrap();
+            insert_original_status(&conn, "test_owner", name, &OriginalStatus::owned()).unwrap();
             insert_repository_primary_language(
                 &mut conn,
                 "test_owner",
@@ -424,8 +1803,9 @@ mod tests {
                 name,
                 dates
                     .iter()
-                    .map(|date| StargazerEntry {
-                        login: format!("test_another_user"),
+                    .enumerate()
+                    .map(|(i, date)| StargazerEntry {
+                        login: format!("user{i}"),
                         starred_at: date.to_string(),
                     })
                     .collect(),
@@ -433,12 +1813,176 @@ mod tests {
             .unwrap();
         }
 
-        let result = collect_star_history_by_language(&mut conn, 10);
+        let result = collect_star_history_by_language(&mut conn, 10, false);
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap().len(), 3);
     }
 
+    #[test]
+    fn test_star_history_by_repository() {
+        let mut conn = setup_test_db();
+
+        let data = [
+            (
+                "repo1",
+                vec!["2024-09-21T11:08:01Z", "2024-09-22T11:08:01Z"],
+            ),
+            ("repo2", vec!["2024-09-21T11:08:01Z"]),
+            (
+                "repo3",
+                vec![
+                    "2024-09-22T11:08:01Z",
+                    "2024-09-22T12:08:01Z",
+                    "2024-09-23T11:08:01Z",
+                ],
+            ),
+        ];
+        for (name, dates) in data {
+            insert_stargazers(
+                &mut conn,
+                "owner",
+                name,
+                dates
+                    .iter()
+                    .enumerate()
+                    .map(|(i, date)| StargazerEntry {
+                        login: format!("user{i}"),
+                        starred_at: date.to_string(),
+                    })
+                    .collect(),
+            )
+            .unwrap();
+        }
+
+        let top =
+            collect_star_history_by_repository(&mut conn, &RepositorySelection::Top(2), false)
+                .unwrap();
+        assert_eq!(
+            top,
+            vec![
+                (date("2024-09-21"), "owner/repo1".to_string(), 1),
+                (date("2024-09-22"), "owner/repo1".to_string(), 2),
+                (date("2024-09-22"), "owner/repo3".to_string(), 2),
+                (date("2024-09-23"), "owner/repo3".to_string(), 3),
+            ]
+        );
+
+        let only = collect_star_history_by_repository(
+            &mut conn,
+            &RepositorySelection::Only(vec![("Owner".to_string(), "REPO2".to_string())]),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            only,
+            vec![(date("2024-09-21"), "owner/repo2".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_select_repositories() {
+        let vec = vec![
+            (date("2024-09-21"), "owner/repo1".to_string(), 1),
+            (date("2024-09-22"), "owner/repo2".to_string(), 1),
+        ];
+        let only = |repositories: &[&str]| {
+            RepositorySelection::Only(
+                repositories
+                    .iter()
+                    .map(|name| ("Owner".to_string(), name.to_string()))
+                    .collect(),
+            )
+        };
+
+        assert_eq!(
+            select_repositories(vec.clone(), &only(&["REPO2"])).unwrap(),
+            vec[1..]
+        );
+        let err = select_repositories(vec, &only(&["repo1", "typo", "missing"])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No stargazers of the repositories: Owner/typo, Owner/missing"
+        );
+    }
+
+    #[test]
+    fn test_daily_new_stars() {
+        let mut conn = setup_test_db();
+
+        insert_stargazers(
+            &mut conn,
+            "owner",
+            "repo",
+            [
+                "2024-09-21T11:08:01Z",
+                "2024-09-21T12:08:01Z",
+                "2024-09-23T11:08:01Z",
+            ]
+            .iter()
+            .enumerate()
+            .map(|(i, date)| StargazerEntry {
+                login: format!("user{i}"),
+                starred_at: date.to_string(),
+            })
+            .collect(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            collect_daily_new_stars(&mut conn).unwrap(),
+            vec![(date("2024-09-21"), 2), (date("2024-09-23"), 1)]
+        );
+    }
+
+    #[test]
+    fn test_stargazer_checkpoints() {
+        let mut conn = setup_test_db();
+
+        let stargazer = |login: &str| StargazerEntry {
+            login: login.to_string(),
+            starred_at: "2024-09-21T11:08:01Z".to_string(),
+        };
+
+        save_stargazers_page(
+            &mut conn,
+            "test_owner",
+            "test_repo",
+            vec![stargazer("user1"), stargazer("user2")],
+            &StargazersProgress::Partial(StargazerCheckpoint {
+                cursor: "cursor1".to_string(),
+                total_count: 4,
+            }),
+        )
+        .unwrap();
+
+        let checkpoint = get_stargazer_checkpoint(&conn, "test_owner", "test_repo")
+            .unwrap()
+            .unwrap();
+        assert_eq!(checkpoint.cursor, "cursor1");
+        assert_eq!(checkpoint.total_count, 4);
+
+        save_stargazers_page(
+            &mut conn,
+            "test_owner",
+            "test_repo",
+            vec![stargazer("user3"), stargazer("user4")],
+            &StargazersProgress::Complete {
+                star_count: Some(4),
+            },
+        )
+        .unwrap();
+
+        assert!(get_stargazer_checkpoint(&conn, "test_owner", "test_repo")
+            .unwrap()
+            .is_none());
+
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM stargazers", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 4);
+    }
+
     #[test]
     fn test_get_newly_starred_original_repositories() {
         let conn = setup_test_db();
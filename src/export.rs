@@ -0,0 +1,145 @@
+//! Export of star histories for external tools.
+//!
+//! Each series is a list of rows, written in one of the following formats:
+//!
+//! - CSV with a header line. Fields containing a comma, a quote or a newline
+//!   are quoted.
+//! - JSON: an array of objects.
+//! - NDJSON: one object per line.
+//!
+//! Rows of the total series have `date` (`YYYY-MM-DD`) and `stars`, the
+//! cumulative number of stars on the date. Rows of the language series also
+//! have `language`. Rows are ordered by date.
+
+use std::io::Write;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+/// Format of exported data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Ndjson,
+}
+
+/// A star history to export.
+#[derive(Debug)]
+pub enum Series {
+    Total(Vec<(NaiveDate, i64)>),
+    Language(Vec<(NaiveDate, String, i64)>),
+}
+
+#[derive(Debug, Serialize)]
+struct Row<'a> {
+    date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    stars: i64,
+}
+
+impl Series {
+    fn header(&self) -> &'static [&'static str] {
+        match self {
+            Series::Total(_) => &["date", "stars"],
+            Series::Language(_) => &["date", "language", "stars"],
+        }
+    }
+
+    fn rows(&self) -> Vec<Row<'_>> {
+        match self {
+            Series::Total(vec) => vec
+                .iter()
+                .map(|(date, stars)| Row {
+                    date: date.format("%Y-%m-%d").to_string(),
+                    language: None,
+                    stars: *stars,
+                })
+                .collect(),
+            Series::Language(vec) => vec
+                .iter()
+                .map(|(date, language, stars)| Row {
+                    date: date.format("%Y-%m-%d").to_string(),
+                    language: Some(language.as_str()),
+                    stars: *stars,
+                })
+                .collect(),
+        }
+    }
+
+    /// Write the series in the format.
+    pub fn write(&self, out: &mut impl Write, format: ExportFormat) -> anyhow::Result<()> {
+        let rows = self.rows();
+        match format {
+            ExportFormat::Csv => {
+                writeln!(out, "{}", self.header().join(","))?;
+                for row in rows {
+                    let mut fields = vec![row.date];
+                    fields.extend(row.language.map(escape_csv_field));
+                    fields.push(row.stars.to_string());
+                    writeln!(out, "{}", fields.join(","))?;
+                }
+            }
+            ExportFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, &rows)?;
+                writeln!(out)?;
+            }
+            ExportFormat::Ndjson => {
+                for row in rows {
+                    serde_json::to_writer(&mut *out, &row)?;
+                    writeln!(out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+
+    fn write_to_string(series: &Series, format: ExportFormat) -> String {
+        let mut buf = Vec::new();
+        series.write(&mut buf, format).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_write_series() {
+        let total = Series::Total(vec![(date("2024-09-21"), 1), (date("2024-09-22"), 3)]);
+        assert_eq!(
+            write_to_string(&total, ExportFormat::Csv),
+            "date,stars\n2024-09-21,1\n2024-09-22,3\n"
+        );
+        assert_eq!(
+            write_to_string(&total, ExportFormat::Ndjson),
+            "{\"date\":\"2024-09-21\",\"stars\":1}\n{\"date\":\"2024-09-22\",\"stars\":3}\n"
+        );
+
+        let language = Series::Language(vec![
+            (date("2024-09-21"), "Rust".to_string(), 1),
+            (date("2024-09-21"), "Foo, \"Bar\"".to_string(), 2),
+        ]);
+        assert_eq!(
+            write_to_string(&language, ExportFormat::Csv),
+            "date,language,stars\n2024-09-21,Rust,1\n2024-09-21,\"Foo, \"\"Bar\"\"\",2\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&write_to_string(&language, ExportFormat::Json)).unwrap();
+        assert_eq!(
+            json[0],
+            serde_json::json!({ "date": "2024-09-21", "language": "Rust", "stars": 1 })
+        );
+    }
+}
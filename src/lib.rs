@@ -1,39 +1,89 @@
 //! Business logic of the application.
 
 use anyhow::anyhow;
-use db::{get_newly_starred_original_repositories, StargazerEntry};
+use db::{get_newly_starred_original_repositories, StarCountDiff, StargazerEntry};
 use duckdb::Connection;
-use tracing::info;
+use futures::{stream, StreamExt, TryStreamExt};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::num::NonZeroUsize;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
 
 mod api;
 pub mod chart;
+mod config;
 mod db;
-
-fn check_commit_author(login: &str, commit: &api::github::CommitEntry) -> bool {
-    match commit.author {
-        None => false,
-        Some(ref author) => match author.user {
-            None => false,
-            Some(ref user) => user.login.eq(login),
-        },
-    }
+mod export;
+mod originality;
+mod overrides;
+mod rate;
+mod stats;
+
+pub use chart::{ChartFormat, ChartOptions, ChartStyle, ChartTheme};
+pub use config::{config_path, Config};
+pub use db::{DuplicateStargazers, RepositorySelection};
+pub use export::ExportFormat;
+pub use originality::OriginalityPolicy;
+pub use overrides::{overrides_path, Overrides};
+pub use rate::RatePeriod;
+pub use stats::Stats;
+
+/// Parse a `YYYY-MM-DD` date in tests.
+#[cfg(test)]
+fn date(s: &str) -> chrono::NaiveDate {
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
 }
 
 fn update_star_counts(
     db: &mut Connection,
     repositories: &Vec<api::github::ResponseRepoEntry>,
 ) -> anyhow::Result<()> {
-    db::insert_star_counts(
-        db,
-        &repositories
-            .iter()
-            .map(|repo| db::StarCountEntry {
-                owner: repo.owner.login.as_str(),
-                name: repo.name.as_str(),
-                stargazer_count: repo.stargazer_count,
-            })
-            .collect(),
-    )?;
+    let entries = repositories
+        .iter()
+        .map(|repo| db::StarCountEntry {
+            owner: repo.owner.login.as_str(),
+            name: repo.name.as_str(),
+            stargazer_count: repo.stargazer_count,
+        })
+        .collect();
+    db::insert_star_counts(db, &entries)?;
+    db::insert_star_count_snapshots(db, &entries)?;
+    Ok(())
+}
+
+fn update_repository_identities(
+    db: &mut Connection,
+    repositories: &[api::github::ResponseRepoEntry],
+    overrides: &Overrides,
+) -> anyhow::Result<()> {
+    let identities = repositories
+        .iter()
+        .map(|repo| db::RepositoryIdentity {
+            id: repo.id.as_str(),
+            owner: repo.owner.login.as_str(),
+            name: repo.name.as_str(),
+        })
+        .collect::<Vec<_>>();
+
+    for rename in db::update_repository_identities(db, &identities)? {
+        info!(
+            old_owner = rename.old_owner,
+            old_name = rename.old_name,
+            new_owner = rename.new_owner,
+            new_name = rename.new_name,
+            "repository has been renamed"
+        );
+        if overrides.get(&rename.old_owner, &rename.old_name).is_some() {
+            warn!(
+                owner = rename.old_owner,
+                name = rename.old_name,
+                "the originality override refers to the old name, please update the overrides file"
+            );
+        }
+    }
+
     Ok(())
 }
 
@@ -52,42 +102,153 @@ fn update_primary_languages(
     }
     Ok(())
 }
+/// Check the originality of repositories owned by other users, fetching their
+/// first commits in a single batch.
+async fn check_originality_batch<'a>(
+    github: &api::github::GitHubClient<&str>,
+    policy: &OriginalityPolicy,
+    login: &str,
+    repositories: Vec<(&'a str, &'a str)>,
+) -> anyhow::Result<
+    Vec<(
+        &'a str,
+        &'a str,
+        Result<originality::Decision, api::github::GitHubError>,
+    )>,
+> {
+    let keys = repositories
+        .iter()
+        .map(|(owner, name)| (owner.to_string(), name.to_string()))
+        .collect::<Vec<_>>();
+
+    let results = github
+        .get_first_commits_batch(&keys, policy.sample_size.max(1))
+        .await?;
+
+    Ok(repositories
+        .into_iter()
+        .zip(results)
+        .map(|((owner, name), result)| {
+            (
+                owner,
+                name,
+                result.map(|first_commits| policy.decide(login, first_commits)),
+            )
+        })
+        .collect())
+}
+
 async fn update_original_statuses(
     db: &mut Connection,
     github: &api::github::GitHubClient<&str>,
+    policy: &OriginalityPolicy,
+    overrides: &Overrides,
     login: &str,
     repositories: &Vec<api::github::ResponseRepoEntry>,
+    parallelism: usize,
 ) -> anyhow::Result<()> {
-    let originality_known = db::original_status_keys(db)?;
+    let records = db::original_status_records(db)?;
+
+    // Overrides take precedence over the existing statuses
+    for (owner, name, is_original) in overrides.iter() {
+        let applied = records.iter().any(|record| {
+            record.owner == owner
+                && record.name == name
+                && record.method == Some(db::DecisionMethod::Override)
+                && record.original == is_original
+        });
+        if !applied {
+            info!(
+                owner,
+                name,
+                original = is_original,
+                "overriding the originality"
+            );
+            db::set_original_status(
+                db,
+                owner,
+                name,
+                &db::OriginalStatus::overridden(is_original),
+            )?;
+        }
+    }
 
-    for repo in repositories.iter() {
-        let owner = repo.owner.login.as_str();
-        let name = repo.name.as_str();
-        if !originality_known.contains(&(owner.to_string(), name.to_string())) {
-            let is_original = owner.eq(login) || {
-                let first_commits = github
-                    .get_first_commits(owner.to_string(), name.to_string(), 5)
-                    .await?;
-
-                match first_commits {
-                    None => false,
-                    Some(commits) => {
-                        let same_login_count = commits
-                            .iter()
-                            .filter(|commit| check_commit_author(login, commit))
-                            .count();
-                        (same_login_count / commits.len()) > (1 / 2)
-                    }
+    // Statuses decided by an older heuristic or a long time ago are checked
+    // again, as well as overrides removed from the file
+    let now = chrono::Utc::now().naive_utc();
+    let originality_known = records
+        .into_iter()
+        .filter(|record| match record.method {
+            Some(db::DecisionMethod::Owner) => true,
+            Some(db::DecisionMethod::Override) => {
+                overrides.get(&record.owner, &record.name).is_some()
+            }
+            Some(db::DecisionMethod::Heuristic) | None => {
+                !policy.is_stale(record.heuristic_version, record.decided_at, now)
+            }
+        })
+        .map(|record| (record.owner, record.name))
+        .chain(
+            overrides
+                .iter()
+                .map(|(owner, name, _)| (owner.to_string(), name.to_string())),
+        )
+        .collect::<HashSet<_>>();
+
+    let (owned, others): (Vec<_>, Vec<_>) = repositories
+        .iter()
+        .filter(|repo| {
+            !originality_known.contains(&(repo.owner.login.to_string(), repo.name.to_string()))
+        })
+        .map(|repo| (repo.owner.login.as_str(), repo.name.as_str()))
+        .partition(|(owner, _)| owner.eq(&login));
+
+    // Repositories owned by the user are original without checking commits
+    for (owner, name) in owned {
+        info!(owner, name, "original");
+        db::set_original_status(db, owner, name, &db::OriginalStatus::owned())?;
+    }
+
+    let batches = others
+        .chunks(api::github::BATCH_SIZE)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<_>>();
+
+    let mut results = stream::iter(batches)
+        .map(|batch| check_originality_batch(github, policy, login, batch))
+        .buffer_unordered(parallelism);
+
+    // The statuses are written as the checks complete, so the database is
+    // only accessed from here.
+    while let Some(batch) = results.next().await {
+        for (owner, name, result) in batch? {
+            let decision = match result {
+                Ok(decision) => decision,
+                Err(e) if e.is_permanent() => {
+                    warn!(owner, name, error = %e, "skipping the originality check");
+                    continue;
                 }
+                Err(e) => Err(e)?,
             };
 
-            if is_original {
+            if decision.original {
                 info!(owner, name, "original");
             } else {
                 info!(owner, name, "not original");
             }
 
-            db::insert_original_status(&db, owner, name, is_original)?;
+            db::set_original_status(
+                db,
+                owner,
+                name,
+                &db::OriginalStatus {
+                    original: decision.original,
+                    method: db::DecisionMethod::Heuristic,
+                    heuristic_version: Some(originality::HEURISTIC_VERSION),
+                    sample_size: Some(decision.sample_size as i64),
+                    ratio: decision.ratio,
+                },
+            )?;
         }
     }
 
@@ -96,57 +257,353 @@ async fn update_original_statuses(
 
 const STARGAZERS_PAGE_SIZE: i64 = 20;
 
+/// A page of stargazers to be saved by the database writer.
+struct StargazersPageWrite {
+    owner: String,
+    name: String,
+    items: Vec<StargazerEntry>,
+    progress: db::StargazersProgress,
+}
+
+/// A unit of work in [`update_stargazers`].
+enum StargazersJob {
+    /// Repositories whose new stargazers fit in a single page, fetched in a
+    /// single request.
+    Batch(Vec<StarCountDiff>),
+    /// A repository fetched page by page, possibly from a checkpoint.
+    Single(StarCountDiff, Option<db::StargazerCheckpoint>),
+}
+
+/// Skip the error if it only concerns a single repository.
+fn skip_permanent_error(owner: &str, name: &str, e: anyhow::Error) -> anyhow::Result<()> {
+    match e.downcast_ref::<api::github::GitHubError>() {
+        Some(e) if e.is_permanent() => {
+            warn!(owner, name, error = %e, "skipping stargazers of the repository");
+            Ok(())
+        }
+        _ => Err(e),
+    }
+}
+
 async fn update_stargazers(
     db: &mut Connection,
     github: &api::github::GitHubClient<&str>,
+    parallelism: usize,
 ) -> anyhow::Result<()> {
-    for diff in get_newly_starred_original_repositories(db)?.iter() {
-        let owner = diff.owner.as_str();
-        let name = diff.name.as_str();
+    // Checkpoints are read upfront, as the database is accessed only by the
+    // writer while fetching.
+    let mut jobs = Vec::new();
+    let mut small = Vec::new();
+    for diff in get_newly_starred_original_repositories(db)? {
+        match db::get_stargazer_checkpoint(db, &diff.owner, &diff.name)? {
+            None if diff.new_count - diff.old_count + 5 <= STARGAZERS_PAGE_SIZE => small.push(diff),
+            checkpoint => jobs.push(StargazersJob::Single(diff, checkpoint)),
+        }
+    }
+    while !small.is_empty() {
+        let rest = small.split_off(small.len().min(api::github::BATCH_SIZE));
+        jobs.push(StargazersJob::Batch(std::mem::replace(&mut small, rest)));
+    }
 
-        info!(owner, name, "fetching stargazers");
+    let (sender, mut receiver) = mpsc::channel::<StargazersPageWrite>(parallelism * 2);
+
+    let fetch = async move {
+        stream::iter(jobs.into_iter().map(Ok))
+            .try_for_each_concurrent(parallelism, |job| {
+                let sender = sender.clone();
+                async move {
+                    match job {
+                        StargazersJob::Batch(diffs) => {
+                            fetch_stargazers_batch(github, diffs, &sender).await
+                        }
+                        StargazersJob::Single(diff, checkpoint) => {
+                            fetch_repository_stargazers(github, &diff, checkpoint, &sender)
+                                .await
+                                .or_else(|e| skip_permanent_error(&diff.owner, &diff.name, e))
+                        }
+                    }
+                }
+            })
+            .await
+    };
+
+    let write = async {
+        while let Some(page) = receiver.recv().await {
+            db::save_stargazers_page(db, &page.owner, &page.name, page.items, &page.progress)?;
+            if let db::StargazersProgress::Complete {
+                star_count: Some(count),
+            } = page.progress
+            {
+                info!(
+                    owner = page.owner,
+                    name = page.name,
+                    new = count,
+                    "total stargazer count has been updated"
+                );
+            }
+        }
+        anyhow::Ok(())
+    };
+
+    tokio::try_join!(fetch, write)?;
+
+    Ok(())
+}
+
+/// Fetch the first page of stargazers of multiple repositories in a single
+/// request. Repositories that turn out to have more stargazers than the page,
+/// e.g. if they have been starred since the star counts were fetched, are
+/// continued one by one.
+async fn fetch_stargazers_batch(
+    github: &api::github::GitHubClient<&str>,
+    diffs: Vec<StarCountDiff>,
+    sender: &mpsc::Sender<StargazersPageWrite>,
+) -> anyhow::Result<()> {
+    let requests = diffs
+        .iter()
+        .map(|diff| api::github::StargazersPageRequest {
+            owner: diff.owner.clone(),
+            name: diff.name.clone(),
+            accum_count: diff.old_count,
+            total_count: diff.new_count,
+        })
+        .collect::<Vec<_>>();
+
+    info!(count = requests.len(), "fetching stargazers in a batch");
+
+    let pages = github
+        .get_stargazers_pages_batch(&requests, STARGAZERS_PAGE_SIZE)
+        .await?;
+
+    for (diff, page) in diffs.into_iter().zip(pages) {
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => {
+                skip_permanent_error(&diff.owner, &diff.name, e.into())?;
+                continue;
+            }
+        };
+
+        let accum_count = diff.old_count + page.items.len() as i64;
+        if let Some(checkpoint) = send_stargazers_page(&diff, page, sender).await? {
+            let diff = StarCountDiff {
+                old_count: accum_count,
+                ..diff
+            };
+            fetch_repository_stargazers(github, &diff, Some(checkpoint), sender)
+                .await
+                .or_else(|e| skip_permanent_error(&diff.owner, &diff.name, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch new stargazers of a repository page by page, and send the pages to
+/// the writer. Each page is committed with a checkpoint, so an interrupted
+/// update resumes from the last page.
+async fn fetch_repository_stargazers(
+    github: &api::github::GitHubClient<&str>,
+    diff: &StarCountDiff,
+    checkpoint: Option<db::StargazerCheckpoint>,
+    sender: &mpsc::Sender<StargazersPageWrite>,
+) -> anyhow::Result<()> {
+    let owner = diff.owner.as_str();
+    let name = diff.name.as_str();
+
+    let (mut cursor, mut total_count) = match checkpoint {
+        Some(checkpoint) => {
+            info!(
+                owner,
+                name, "resuming fetching stargazers from the checkpoint"
+            );
+            (Some(checkpoint.cursor), checkpoint.total_count)
+        }
+        None => {
+            info!(owner, name, "fetching stargazers");
+            (None, diff.new_count)
+        }
+    };
+    let mut accum_count = diff.old_count;
 
-        let (new_total_count, new_items) = github
-            .get_stargazers_after_count(
+    loop {
+        let page = github
+            .get_stargazers_page(
                 owner.to_string(),
                 name.to_string(),
-                diff.old_count,
-                diff.new_count,
+                accum_count,
+                total_count,
                 STARGAZERS_PAGE_SIZE,
+                cursor,
             )
             .await?;
 
-        db::insert_stargazers(
+        total_count = page.total_count;
+        accum_count += page.items.len() as i64;
+
+        match send_stargazers_page(diff, page, sender).await? {
+            Some(checkpoint) => cursor = Some(checkpoint.cursor),
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Send a page to the writer, and return the checkpoint for fetching the rest
+/// of the stargazers, if any.
+async fn send_stargazers_page(
+    diff: &StarCountDiff,
+    page: api::github::StargazersPage,
+    sender: &mpsc::Sender<StargazersPageWrite>,
+) -> anyhow::Result<Option<db::StargazerCheckpoint>> {
+    let items = page
+        .items
+        .iter()
+        .map(|x| StargazerEntry {
+            login: x.node.login.to_owned(),
+            starred_at: x.starred_at.to_owned(),
+        })
+        .collect();
+
+    let checkpoint = page.previous_cursor.map(|cursor| db::StargazerCheckpoint {
+        cursor,
+        total_count: page.total_count,
+    });
+
+    let progress = match checkpoint {
+        Some(ref checkpoint) => db::StargazersProgress::Partial(checkpoint.clone()),
+        None => db::StargazersProgress::Complete {
+            star_count: (page.total_count > diff.new_count).then_some(page.total_count),
+        },
+    };
+
+    sender
+        .send(StargazersPageWrite {
+            owner: diff.owner.clone(),
+            name: diff.name.clone(),
+            items,
+            progress,
+        })
+        .await
+        .map_err(|_| anyhow!("the database writer has stopped"))?;
+
+    Ok(checkpoint)
+}
+
+/// Compare the stored stargazers with the full list for repositories that
+/// have lost stars or have not been reconciled for the interval, to detect
+/// unstars.
+async fn reconcile_stargazers(
+    db: &mut Connection,
+    github: &api::github::GitHubClient<&str>,
+    interval: Option<chrono::TimeDelta>,
+    parallelism: usize,
+) -> anyhow::Result<()> {
+    let due_before = interval.map(|interval| chrono::Utc::now().naive_utc() - interval);
+    let repositories = db::get_repositories_to_reconcile(db, due_before)?;
+
+    let mut results = stream::iter(repositories)
+        .map(|(owner, name)| async move {
+            let result = github.get_all_stargazers(owner.clone(), name.clone()).await;
+            (owner, name, result)
+        })
+        .buffer_unordered(parallelism);
+
+    while let Some((owner, name, result)) = results.next().await {
+        let owner = owner.as_str();
+        let name = name.as_str();
+        let current = match result {
+            Ok(items) => items,
+            Err(e) if e.is_permanent() => {
+                warn!(owner, name, error = %e, "skipping reconciling stargazers");
+                continue;
+            }
+            Err(e) => Err(e)?,
+        };
+
+        let (unstarred, added) = db::reconcile_stargazers(
             db,
             owner,
             name,
-            new_items
-                .iter()
+            current
+                .into_iter()
                 .map(|x| StargazerEntry {
-                    login: x.node.login.to_owned(),
-                    starred_at: x.starred_at.to_owned(),
+                    login: x.node.login,
+                    starred_at: x.starred_at,
                 })
                 .collect(),
         )?;
 
-        if new_total_count > diff.new_count {
-            db::update_star_count(db, owner, name, new_total_count)?;
-            info!(
-                old = diff.new_count,
-                new = new_total_count,
-                "total stargazer count has been updated"
-            );
-        }
+        info!(owner, name, unstarred, added, "reconciled stargazers");
     }
 
     Ok(())
 }
 
-pub async fn update_database(db: &mut Connection) -> anyhow::Result<()> {
-    db::setup(db);
+/// Options of [`update_database`].
+#[derive(Debug, Clone)]
+pub struct UpdateOptions {
+    /// Maximum number of attempts per API request on transient errors.
+    pub max_attempts: u32,
+    /// Maximum number of repositories processed concurrently. Values below 1
+    /// are treated as 1.
+    pub parallelism: usize,
+    /// Policy for checking the originality of repositories owned by other
+    /// accounts.
+    pub originality: OriginalityPolicy,
+    /// Originality of repositories set manually, which skips the check.
+    pub overrides: Overrides,
+    /// Stargazers of each repository are compared with the full list at this
+    /// interval to detect unstars. If `None`, they are compared only when the
+    /// star count drops below the number of stored stargazers.
+    pub reconcile_interval: Option<chrono::TimeDelta>,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        Self {
+            max_attempts: api::github::RetryPolicy::default().max_attempts,
+            parallelism: 4,
+            originality: OriginalityPolicy::default(),
+            overrides: Overrides::default(),
+            reconcile_interval: Some(chrono::TimeDelta::days(30)),
+        }
+    }
+}
+
+/// Open a database, migrating its schema to the latest version.
+pub fn open_database(path: &str) -> anyhow::Result<Connection> {
+    let mut conn = Connection::open(path)?;
+    db::setup(&mut conn)?;
+    Ok(conn)
+}
+
+/// Remove duplicate stargazer rows from the database at the path, and bring
+/// its schema up to date. Return the number of rows removed from each
+/// repository.
+pub fn dedupe_database(path: &str) -> anyhow::Result<Vec<DuplicateStargazers>> {
+    let mut conn = Connection::open(path)?;
+    let duplicates = db::dedupe_stargazers(&mut conn)?;
+    db::setup(&mut conn)?;
+    Ok(duplicates)
+}
+
+/// Collect the summary of the database, with at most `top` repositories.
+pub fn collect_stats(db: &Connection, top: usize) -> anyhow::Result<Stats> {
+    Stats::collect(db, top, chrono::Utc::now().naive_utc())
+}
+
+pub async fn update_database(db: &mut Connection, options: &UpdateOptions) -> anyhow::Result<()> {
+    let parallelism = options.parallelism.max(1);
 
-    let github = api::github::GitHubClient::default()?;
+    let github =
+        api::github::GitHubClient::default()?.with_retry_policy(api::github::RetryPolicy {
+            max_attempts: options.max_attempts,
+            ..Default::default()
+        });
 
+    github.set_phase("repos");
     let (login, repositories) = github.get_all_starred_own_repositories().await?;
 
     info!(
@@ -155,41 +612,288 @@ pub async fn update_database(db: &mut Connection) -> anyhow::Result<()> {
         "fetched starred repositories"
     );
 
+    update_repository_identities(db, &repositories, &options.overrides)?;
+    db::clear_repositories(db)?;
     update_star_counts(db, &repositories)?;
     update_primary_languages(db, &repositories)?;
-    update_original_statuses(db, &github, login.as_str(), &repositories).await?;
 
-    update_stargazers(db, &github).await?;
+    github.set_phase("originality");
+    update_original_statuses(
+        db,
+        &github,
+        &options.originality,
+        &options.overrides,
+        login.as_str(),
+        &repositories,
+        parallelism,
+    )
+    .await?;
+
+    github.set_phase("stargazers");
+    update_stargazers(db, &github, parallelism).await?;
+
+    github.set_phase("reconcile");
+    reconcile_stargazers(db, &github, options.reconcile_interval, parallelism).await?;
 
     info!("finished updating the database");
 
+    for (phase, points) in github.points_by_phase() {
+        info!(phase, points, "rate limit points spent");
+    }
+    if let Some(remaining) = github.remaining_points() {
+        info!(remaining, "rate limit points remaining");
+    }
+
     Ok(())
 }
 
-pub fn render_star_history_by_language(db: &mut Connection, path: &str) -> anyhow::Result<()> {
-    let vec = db::collect_star_history_by_language(db, 10)?;
+/// Override the originality of a repository given as `owner/name`. The status
+/// in the database is updated as well.
+pub fn set_original_override(
+    db: &mut Connection,
+    path: &std::path::Path,
+    repository: &str,
+    is_original: bool,
+) -> anyhow::Result<()> {
+    let (owner, name) = overrides::parse_repository(repository)?;
+    let mut overrides = Overrides::load(path)?;
+    overrides.set(&owner, &name, is_original);
+    overrides.save(path)?;
+
+    db::set_original_status(
+        db,
+        &owner,
+        &name,
+        &db::OriginalStatus::overridden(is_original),
+    )?;
+
+    info!(owner, name, original = is_original, "set the override");
+
+    Ok(())
+}
+
+/// Remove the override of a repository given as `owner/name`. The status in
+/// the database is removed, so the heuristic decides it on the next update.
+pub fn unset_original_override(
+    db: &mut Connection,
+    path: &std::path::Path,
+    repository: &str,
+) -> anyhow::Result<()> {
+    let (owner, name) = overrides::parse_repository(repository)?;
+    let mut overrides = Overrides::load(path)?;
+    if !overrides.unset(&owner, &name) {
+        Err(anyhow!("No override for {}", repository))?;
+    }
+    overrides.save(path)?;
+
+    db::delete_original_status(db, &owner, &name)?;
+
+    info!(owner, name, "removed the override");
+
+    Ok(())
+}
+
+/// Minimum number of stars of a language to be included in the language
+/// histories.
+const MIN_LANGUAGE_STARS: i64 = 10;
+
+/// Save a chart in each of the themes. If there are multiple themes, the name
+/// of each theme is added to the path. Nothing is saved unless all of the
+/// themes can be applied to the format.
+fn save_chart_variants(
+    path: &str,
+    format: ChartFormat,
+    themes: &[ChartTheme],
+    save: impl Fn(&str, ChartTheme) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    for &theme in themes {
+        chart::check_theme(format, theme)?;
+    }
+    for &theme in themes {
+        let path = if themes.len() > 1 {
+            chart::themed_path(path, theme)
+        } else {
+            path.to_string()
+        };
+        save(&path, theme)?;
+        info!(path, "saved the image");
+    }
+    Ok(())
+}
+
+/// Render the star history by language. If `subtract_unstars` is true,
+/// detected unstars are subtracted.
+pub fn render_star_history_by_language(
+    db: &mut Connection,
+    path: &str,
+    format: ChartFormat,
+    themes: &[ChartTheme],
+    options: &ChartOptions,
+    subtract_unstars: bool,
+) -> anyhow::Result<()> {
+    let vec = db::collect_star_history_by_language(db, MIN_LANGUAGE_STARS, subtract_unstars)?;
 
     if vec.len() < 2 {
         Err(anyhow!("No meaningful data"))?;
     }
 
-    chart::draw_star_history_by_language(vec, path)?;
+    save_chart_variants(path, format, themes, |path, theme| {
+        chart::save_star_history_by_language(vec.clone(), path, format, theme, options)
+    })
+}
 
-    info!(path, "saved the image");
+/// Render the total star history. If `subtract_unstars` is true, detected
+/// unstars are subtracted.
+pub fn render_total_star_history(
+    db: &mut Connection,
+    path: &str,
+    format: ChartFormat,
+    themes: &[ChartTheme],
+    options: &ChartOptions,
+    subtract_unstars: bool,
+) -> anyhow::Result<()> {
+    let vec = db::collect_total_star_history(db, subtract_unstars)?;
 
-    Ok(())
+    if vec.len() < 2 {
+        Err(anyhow!("No meaningful data"))?;
+    }
+
+    save_chart_variants(path, format, themes, |path, theme| {
+        chart::save_total_star_history(vec.clone(), path, format, theme, options)
+    })
+}
+
+/// Render the star history of the repositories. If `subtract_unstars` is
+/// true, detected unstars are subtracted.
+pub fn render_star_history_by_repository(
+    db: &mut Connection,
+    path: &str,
+    format: ChartFormat,
+    themes: &[ChartTheme],
+    options: &ChartOptions,
+    selection: &RepositorySelection,
+    subtract_unstars: bool,
+) -> anyhow::Result<()> {
+    let vec = db::collect_star_history_by_repository(db, selection, subtract_unstars)?;
+
+    if vec.len() < 2 {
+        Err(anyhow!("No meaningful data"))?;
+    }
+
+    save_chart_variants(path, format, themes, |path, theme| {
+        chart::save_star_history_by_repository(vec.clone(), path, format, theme, options)
+    })
+}
+
+/// Render the number of new stars per period, with the moving average over
+/// `window` periods if given.
+pub fn render_star_rate(
+    db: &mut Connection,
+    path: &str,
+    format: ChartFormat,
+    themes: &[ChartTheme],
+    options: &ChartOptions,
+    period: RatePeriod,
+    window: Option<NonZeroUsize>,
+) -> anyhow::Result<()> {
+    let daily = db::collect_daily_new_stars(db)?;
+    let rate = rate::StarRate::new(&daily, period, window);
+
+    if rate.counts.is_empty() {
+        Err(anyhow!("No meaningful data"))?;
+    }
+
+    save_chart_variants(path, format, themes, |path, theme| {
+        chart::save_star_rate(&rate, path, format, theme, options)
+    })
 }
 
-pub fn render_total_star_history(db: &mut Connection, path: &str) -> anyhow::Result<()> {
-    let vec = db::collect_total_star_history(db)?;
+/// Parse repositories given as `owner/name` into a selection.
+pub fn parse_repository_selection(repositories: &[String]) -> anyhow::Result<RepositorySelection> {
+    let repositories = repositories
+        .iter()
+        .map(|repository| overrides::parse_repository(repository))
+        .collect::<anyhow::Result<_>>()?;
+    Ok(RepositorySelection::Only(repositories))
+}
+
+pub fn render_net_star_history(
+    db: &mut Connection,
+    path: &str,
+    format: ChartFormat,
+    themes: &[ChartTheme],
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    let vec = db::collect_net_star_history(db)?;
 
     if vec.len() < 2 {
         Err(anyhow!("No meaningful data"))?;
     }
 
-    chart::draw_total_star_history(vec, path)?;
+    save_chart_variants(path, format, themes, |path, theme| {
+        chart::save_net_star_history(vec.clone(), path, format, theme, options)
+    })
+}
+
+/// Export the total star history to the path, or to stdout if `None`.
+pub fn export_total_star_history(
+    db: &mut Connection,
+    path: Option<&str>,
+    format: ExportFormat,
+    subtract_unstars: bool,
+) -> anyhow::Result<()> {
+    let vec = db::collect_total_star_history(db, subtract_unstars)?;
+    write_series(export::Series::Total(vec), path, format)
+}
 
-    info!(path, "saved the image");
+/// Export the star history by language to the path, or to stdout if `None`.
+pub fn export_star_history_by_language(
+    db: &mut Connection,
+    path: Option<&str>,
+    format: ExportFormat,
+    subtract_unstars: bool,
+) -> anyhow::Result<()> {
+    let vec = db::collect_star_history_by_language(db, MIN_LANGUAGE_STARS, subtract_unstars)?;
+    write_series(export::Series::Language(vec), path, format)
+}
 
+fn write_series(
+    series: export::Series,
+    path: Option<&str>,
+    format: ExportFormat,
+) -> anyhow::Result<()> {
+    match path {
+        Some(path) => {
+            let mut file = BufWriter::new(File::create(path)?);
+            series.write(&mut file, format)?;
+            file.flush()?;
+            info!(path, "exported the data");
+        }
+        None => {
+            series.write(&mut std::io::stdout().lock(), format)?;
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_save_chart_variants() {
+        let saved = RefCell::new(Vec::new());
+        let save = |path: &str, _| {
+            saved.borrow_mut().push(path.to_string());
+            Ok(())
+        };
+
+        let themes = [ChartTheme::Light, ChartTheme::Dark];
+        assert!(save_chart_variants("total.json", ChartFormat::Chartjs, &themes, save).is_err());
+        assert!(saved.borrow().is_empty());
+
+        save_chart_variants("total.svg", ChartFormat::Svg, &themes, save).unwrap();
+        assert_eq!(*saved.borrow(), vec!["total-light.svg", "total-dark.svg"]);
+    }
+}
@@ -1,11 +1,27 @@
 use clap::Parser;
 use clap::Subcommand;
 use clap::ValueEnum;
+use std::num::NonZeroUsize;
 use tokio;
 use tracing::info;
+use yastar::collect_stats;
+use yastar::dedupe_database;
+use yastar::open_database;
+use yastar::render_net_star_history;
 use yastar::render_star_history_by_language;
+use yastar::render_star_history_by_repository;
+use yastar::render_star_rate;
 use yastar::render_total_star_history;
 use yastar::update_database;
+use yastar::OriginalityPolicy;
+use yastar::Overrides;
+use yastar::RatePeriod;
+use yastar::UpdateOptions;
+use yastar::{config_path, Config};
+use yastar::{export_star_history_by_language, export_total_star_history, ExportFormat};
+use yastar::{overrides_path, set_original_override, unset_original_override};
+use yastar::{parse_repository_selection, RepositorySelection};
+use yastar::{ChartFormat, ChartOptions, ChartStyle, ChartTheme};
 
 #[derive(Parser, Debug)]
 #[command(name = "yastar")]
@@ -18,13 +34,105 @@ struct Cli {
 #[derive(Debug, ValueEnum, Copy, Clone, PartialEq, Eq)]
 enum HistoryChartType {
     Language,
+    /// Stars of the top repositories, or the ones given by --repo
+    Repository,
     Total,
+    /// Total of the star counts, which reflects unstars
+    Net,
+    /// New stars per period, given by --period
+    Rate,
+}
+
+#[derive(Debug, ValueEnum, Copy, Clone, PartialEq, Eq)]
+enum ExportSeries {
+    /// Cumulative stars of all original repositories
+    Total,
+    /// Cumulative stars per language
+    Language,
+}
+
+#[derive(Debug, ValueEnum, Copy, Clone, PartialEq, Eq)]
+enum AuthorMatch {
+    /// Login of the GitHub account linked to the commit author
+    Login,
+    /// Email address of the commit author
+    Email,
+    /// Email addresses in Co-authored-by trailers
+    CoAuthor,
+}
+
+#[derive(Debug, ValueEnum, Copy, Clone, PartialEq, Eq)]
+enum StatsFormat {
+    Table,
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Update the local database specified in the config.
-    Update,
+    Update {
+        #[arg(
+            long,
+            help = "Maximum number of attempts per API request on transient errors",
+            default_value_t = UpdateOptions::default().max_attempts,
+            value_name = "N"
+        )]
+        max_attempts: u32,
+        #[arg(
+            long,
+            short = 'j',
+            help = "Maximum number of repositories processed concurrently",
+            default_value_t = UpdateOptions::default().parallelism,
+            value_name = "N"
+        )]
+        parallelism: usize,
+        #[arg(
+            long,
+            help = "Number of first commits checked to decide the originality of a repository",
+            default_value_t = OriginalityPolicy::default().sample_size,
+            value_name = "N"
+        )]
+        sample_size: usize,
+        #[arg(
+            long,
+            help = "Ratio of the commits by yourself above which a repository is original",
+            default_value_t = OriginalityPolicy::default().threshold,
+            value_parser = parse_ratio,
+            value_name = "RATIO"
+        )]
+        threshold: f64,
+        #[arg(
+            long,
+            help = "How commits are attributed to yourself",
+            value_delimiter = ',',
+            default_value = "login",
+            value_enum,
+            value_name = "METHODS"
+        )]
+        match_by: Vec<AuthorMatch>,
+        #[arg(
+            long = "email",
+            help = "Your email address used in commits (can be repeated)",
+            value_name = "EMAIL"
+        )]
+        emails: Vec<String>,
+        #[arg(
+            long,
+            help = "Re-check originality decided by commits more than DAYS ago (0 to disable)",
+            default_value_t = OriginalityPolicy::default().max_age.map_or(0, |age| age.num_days()),
+            value_parser = clap::value_parser!(i64).range(0..),
+            value_name = "DAYS"
+        )]
+        recheck_after: i64,
+        #[arg(
+            long,
+            help = "Compare stargazers with the full list to detect unstars every DAYS (0 to only do so when the star count drops)",
+            default_value_t = UpdateOptions::default().reconcile_interval.map_or(0, |interval| interval.num_days()),
+            value_parser = clap::value_parser!(i64).range(0..),
+            value_name = "DAYS"
+        )]
+        reconcile_after: i64,
+    },
 
     // Render a chart to the given file
     Chart {
@@ -40,12 +148,223 @@ enum Commands {
             value_name = "TYPE"
         )]
         chart_type: HistoryChartType,
+        #[arg(
+            long,
+            help = "Set the output format [default: inferred from the extension of PATH, or svg]",
+            value_enum,
+            value_name = "FORMAT"
+        )]
+        format: Option<ChartFormat>,
+        #[arg(
+            long = "theme",
+            help = "Set the colour theme of images. If multiple themes are given, each variant is saved with the name of the theme added to PATH",
+            default_value = "light",
+            value_delimiter = ',',
+            value_enum,
+            value_name = "THEME"
+        )]
+        themes: Vec<ChartTheme>,
+        #[command(flatten)]
+        options: ChartOptionArgs,
+        #[arg(
+            long,
+            help = "Number of repositories to show in the repository chart",
+            default_value_t = 5,
+            value_name = "N"
+        )]
+        top: usize,
+        #[arg(
+            long = "repo",
+            help = "Show the repository in the repository chart instead of the top ones (can be repeated)",
+            value_name = "OWNER/NAME"
+        )]
+        repositories: Vec<String>,
+        #[arg(
+            long,
+            help = "Period to count new stars in for the rate chart",
+            default_value_t = RatePeriod::Week,
+            value_enum,
+            value_name = "PERIOD"
+        )]
+        period: RatePeriod,
+        #[arg(
+            long,
+            help = "Draw the moving average over N periods in the rate chart",
+            value_name = "N"
+        )]
+        moving_average: Option<NonZeroUsize>,
+        #[arg(
+            long,
+            help = "Subtract detected unstars from the language, repository and total histories"
+        )]
+        subtract_unstars: bool,
+    },
+
+    /// Export a star history as data for external chart tools.
+    Export {
+        #[arg(help = "Write to the file instead of stdout")]
+        path: Option<String>,
+        #[arg(
+            long,
+            help = "Set the series to export",
+            default_value_t = ExportSeries::Total,
+            value_enum,
+            value_name = "SERIES"
+        )]
+        series: ExportSeries,
+        #[arg(
+            long,
+            help = "Set the output format",
+            default_value_t = ExportFormat::Csv,
+            value_enum,
+            value_name = "FORMAT"
+        )]
+        format: ExportFormat,
+        #[arg(long, help = "Subtract detected unstars from the history")]
+        subtract_unstars: bool,
+    },
+
+    /// Print a summary of the database.
+    Stats {
+        #[arg(
+            long,
+            help = "Set the output format",
+            default_value_t = StatsFormat::Table,
+            value_enum,
+            value_name = "FORMAT"
+        )]
+        format: StatsFormat,
+        #[arg(
+            long,
+            help = "Number of top repositories to show",
+            default_value_t = 10,
+            value_name = "N"
+        )]
+        top: usize,
+    },
+
+    /// Manage manual overrides of the originality of repositories.
+    Original {
+        #[command(subcommand)]
+        command: OriginalCommands,
+    },
+
+    /// Maintain the database.
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
     },
 
     /// Print the config.
     Config,
 }
 
+#[derive(Subcommand, Debug)]
+enum DbCommands {
+    /// Remove duplicate stargazer rows, which prevent migrating the database.
+    Dedupe,
+}
+
+/// Options of charts, which override the config file.
+#[derive(clap::Args, Debug)]
+struct ChartOptionArgs {
+    #[arg(long, help = "Width of the chart in pixels", value_name = "PIXELS")]
+    width: Option<u32>,
+    #[arg(long, help = "Height of the chart in pixels", value_name = "PIXELS")]
+    height: Option<u32>,
+    #[arg(long, help = "Title of the chart (an empty string hides it)")]
+    title: Option<String>,
+    #[arg(long, help = "Subtitle of the chart")]
+    subtitle: Option<String>,
+    #[arg(long, help = "Font family of the text", value_name = "FAMILY")]
+    font_family: Option<String>,
+    #[arg(long, help = "Font size of the title", value_name = "PIXELS")]
+    title_font_size: Option<u32>,
+    #[arg(
+        long,
+        help = "Font size of the subtitle, axis labels and legends",
+        value_name = "PIXELS"
+    )]
+    label_font_size: Option<u32>,
+    #[arg(long, help = "Margin at the top of the chart", value_name = "PIXELS")]
+    margin_top: Option<u32>,
+    #[arg(long, help = "Margin at the right of the chart", value_name = "PIXELS")]
+    margin_right: Option<u32>,
+    #[arg(
+        long,
+        help = "Margin at the bottom of the chart",
+        value_name = "PIXELS"
+    )]
+    margin_bottom: Option<u32>,
+    #[arg(long, help = "Margin at the left of the chart", value_name = "PIXELS")]
+    margin_left: Option<u32>,
+    #[arg(
+        long,
+        help = "Maximum number of labels on the x axis",
+        value_name = "N"
+    )]
+    x_labels: Option<usize>,
+    #[arg(
+        long,
+        help = "Maximum number of labels on the y axis",
+        value_name = "N"
+    )]
+    y_labels: Option<usize>,
+    #[arg(
+        long,
+        help = "Style of the language and repository charts",
+        value_enum,
+        value_name = "STYLE"
+    )]
+    style: Option<ChartStyle>,
+}
+
+impl ChartOptionArgs {
+    fn apply(self, options: &mut ChartOptions) {
+        fn set<T>(target: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *target = value;
+            }
+        }
+        set(&mut options.width, self.width);
+        set(&mut options.height, self.height);
+        if self.title.is_some() {
+            options.title = self.title;
+        }
+        if self.subtitle.is_some() {
+            options.subtitle = self.subtitle;
+        }
+        set(&mut options.font_family, self.font_family);
+        set(&mut options.title_font_size, self.title_font_size);
+        set(&mut options.label_font_size, self.label_font_size);
+        set(&mut options.margin_top, self.margin_top);
+        set(&mut options.margin_right, self.margin_right);
+        set(&mut options.margin_bottom, self.margin_bottom);
+        set(&mut options.margin_left, self.margin_left);
+        set(&mut options.x_labels, self.x_labels);
+        set(&mut options.y_labels, self.y_labels);
+        set(&mut options.style, self.style);
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum OriginalCommands {
+    /// Override the originality of a repository.
+    Set {
+        #[arg(value_name = "OWNER/NAME")]
+        repository: String,
+        #[arg(value_parser = clap::value_parser!(bool), value_name = "true|false")]
+        original: bool,
+    },
+    /// Remove the override of a repository.
+    Unset {
+        #[arg(value_name = "OWNER/NAME")]
+        repository: String,
+    },
+    /// List the overrides.
+    List,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // continue even if .env does not exist
@@ -55,22 +374,183 @@ async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Update => {
+        Commands::Update {
+            max_attempts,
+            parallelism,
+            sample_size,
+            threshold,
+            match_by,
+            emails,
+            recheck_after,
+            reconcile_after,
+        } => {
             let mut duckdb = duckdb_open_env()?;
-            update_database(&mut duckdb).await?;
+            let options = UpdateOptions {
+                max_attempts,
+                parallelism,
+                originality: OriginalityPolicy {
+                    sample_size,
+                    threshold,
+                    match_login: match_by.contains(&AuthorMatch::Login),
+                    match_email: match_by.contains(&AuthorMatch::Email),
+                    match_co_authors: match_by.contains(&AuthorMatch::CoAuthor),
+                    emails,
+                    max_age: (recheck_after > 0).then(|| chrono::TimeDelta::days(recheck_after)),
+                },
+                overrides: match overrides_path() {
+                    Some(path) => Overrides::load(&path)?,
+                    None => Overrides::default(),
+                },
+                reconcile_interval: (reconcile_after > 0)
+                    .then(|| chrono::TimeDelta::days(reconcile_after)),
+            };
+            update_database(&mut duckdb, &options).await?;
+        }
+        Commands::Original { command } => {
+            let path = overrides_path().ok_or_else(|| {
+                anyhow::anyhow!("Set YASTAR_OVERRIDES_FILE to the path of the overrides file")
+            })?;
+            match command {
+                OriginalCommands::Set {
+                    repository,
+                    original,
+                } => {
+                    let mut duckdb = duckdb_open_env()?;
+                    set_original_override(&mut duckdb, &path, &repository, original)?;
+                }
+                OriginalCommands::Unset { repository } => {
+                    let mut duckdb = duckdb_open_env()?;
+                    unset_original_override(&mut duckdb, &path, &repository)?;
+                }
+                OriginalCommands::List => {
+                    for (owner, name, original) in Overrides::load(&path)?.iter() {
+                        println!("{}/{}\t{}", owner, name, original);
+                    }
+                }
+            }
+        }
+        Commands::Export {
+            path,
+            series,
+            format,
+            subtract_unstars,
+        } => {
+            let mut duckdb = duckdb_open_env()?;
+            let path = path.as_deref();
+            match series {
+                ExportSeries::Total => {
+                    export_total_star_history(&mut duckdb, path, format, subtract_unstars)?;
+                }
+                ExportSeries::Language => {
+                    export_star_history_by_language(&mut duckdb, path, format, subtract_unstars)?;
+                }
+            }
+        }
+        Commands::Stats { format, top } => {
+            let duckdb = duckdb_open_env()?;
+            let stats = collect_stats(&duckdb, top)?;
+            match format {
+                StatsFormat::Table => print!("{}", stats.to_table()),
+                StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+            }
         }
+        Commands::Db { command } => match command {
+            DbCommands::Dedupe => {
+                let conn_string = duckdb_connection()?;
+                let duplicates = dedupe_database(&conn_string)?;
+                for entry in duplicates.iter() {
+                    println!("{}/{}\t{}", entry.owner, entry.name, entry.count);
+                }
+                println!(
+                    "Removed {} duplicate stargazer rows",
+                    duplicates.iter().map(|entry| entry.count).sum::<usize>()
+                );
+            }
+        },
         Commands::Config => {
             let conn_string = duckdb_connection()?;
             println!("Database (duckdb): {}", conn_string);
+            if let Some(path) = overrides_path() {
+                println!("Originality overrides: {}", path.display());
+            }
+            if let Some(path) = config_path() {
+                println!("Config file: {}", path.display());
+            }
         }
-        Commands::Chart { chart_type, path } => {
+        Commands::Chart {
+            chart_type,
+            path,
+            format,
+            themes,
+            options,
+            top,
+            repositories,
+            period,
+            moving_average,
+            subtract_unstars,
+        } => {
+            let mut chart_options = load_config()?.chart;
+            options.apply(&mut chart_options);
+            let format = format
+                .or_else(|| ChartFormat::from_path(&path))
+                .unwrap_or(ChartFormat::Svg);
             let mut duckdb = duckdb_open_env()?;
             match chart_type {
                 HistoryChartType::Language => {
-                    render_star_history_by_language(&mut duckdb, path.as_str())?;
+                    render_star_history_by_language(
+                        &mut duckdb,
+                        path.as_str(),
+                        format,
+                        &themes,
+                        &chart_options,
+                        subtract_unstars,
+                    )?;
+                }
+                HistoryChartType::Repository => {
+                    let selection = if repositories.is_empty() {
+                        RepositorySelection::Top(top)
+                    } else {
+                        parse_repository_selection(&repositories)?
+                    };
+                    render_star_history_by_repository(
+                        &mut duckdb,
+                        path.as_str(),
+                        format,
+                        &themes,
+                        &chart_options,
+                        &selection,
+                        subtract_unstars,
+                    )?;
                 }
                 HistoryChartType::Total => {
-                    render_total_star_history(&mut duckdb, path.as_str())?;
+                    render_total_star_history(
+                        &mut duckdb,
+                        path.as_str(),
+                        format,
+                        &themes,
+                        &chart_options,
+                        subtract_unstars,
+                    )?;
+                }
+                HistoryChartType::Rate => {
+                    render_star_rate(
+                        &mut duckdb,
+                        path.as_str(),
+                        format,
+                        &themes,
+                        &chart_options,
+                        period,
+                        moving_average,
+                    )?;
+                }
+                HistoryChartType::Net => {
+                    render_net_star_history(
+                        &mut duckdb,
+                        path.as_str(),
+                        format,
+                        &themes,
+                        &chart_options,
+                    )?;
                 }
             }
         }
@@ -79,10 +559,26 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if (0.0..1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{} is not in the range [0, 1)", value))
+    }
+}
+
+fn load_config() -> anyhow::Result<Config> {
+    match config_path() {
+        Some(path) => Config::load(&path),
+        None => Ok(Config::default()),
+    }
+}
+
 fn duckdb_open_env() -> anyhow::Result<duckdb::Connection> {
     let conn_string = duckdb_connection()?;
     info!(path = conn_string, "opening database");
-    let conn = duckdb::Connection::open(conn_string)?;
+    let conn = open_database(&conn_string)?;
     Ok(conn)
 }
 
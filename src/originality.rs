@@ -0,0 +1,270 @@
+//! Heuristic for deciding whether a repository is original to the user.
+
+use chrono::{NaiveDateTime, TimeDelta};
+
+use crate::api::github::CommitEntry;
+
+/// Version of the heuristic recorded with each status. Bump it when the
+/// heuristic changes, so statuses decided by older versions are re-checked.
+/// Statuses saved without a version were decided by version 1, which
+/// required all of the commits to be authored by the user.
+pub const HEURISTIC_VERSION: i64 = 2;
+
+/// Suffix of the no-reply addresses GitHub assigns to users, either
+/// `LOGIN@users.noreply.github.com` or `ID+LOGIN@users.noreply.github.com`.
+const NOREPLY_DOMAIN: &str = "@users.noreply.github.com";
+
+const CO_AUTHOR_TRAILER: &str = "co-authored-by:";
+
+/// Policy for deciding whether a repository owned by another account is
+/// original to the user, based on the authors of its first commits.
+#[derive(Debug, Clone)]
+pub struct OriginalityPolicy {
+    /// Number of first commits to check.
+    pub sample_size: usize,
+    /// The repository is original if the ratio of the commits authored by the
+    /// user is greater than this value.
+    pub threshold: f64,
+    /// Match the login of the GitHub account linked to the commit author.
+    pub match_login: bool,
+    /// Match the email address of the commit author.
+    pub match_email: bool,
+    /// Match the email addresses in `Co-authored-by` trailers of the commit
+    /// message.
+    pub match_co_authors: bool,
+    /// Email addresses of the user. No-reply addresses of the login are always
+    /// matched.
+    pub emails: Vec<String>,
+    /// Statuses decided by the heuristic earlier than this are re-checked. If
+    /// `None`, they are re-checked only when the heuristic changes.
+    pub max_age: Option<TimeDelta>,
+}
+
+/// Result of the heuristic.
+#[derive(Debug)]
+pub struct Decision {
+    pub original: bool,
+    /// Number of commits checked.
+    pub sample_size: usize,
+    /// Ratio of the commits authored by the user, if any commit is checked.
+    pub ratio: Option<f64>,
+}
+
+impl Default for OriginalityPolicy {
+    fn default() -> Self {
+        Self {
+            sample_size: 5,
+            threshold: 0.5,
+            match_login: true,
+            match_email: false,
+            match_co_authors: false,
+            emails: Vec::new(),
+            max_age: Some(TimeDelta::days(180)),
+        }
+    }
+}
+
+impl OriginalityPolicy {
+    /// Decide the originality from the first commits of a repository.
+    pub fn decide(&self, login: &str, first_commits: Option<Vec<CommitEntry>>) -> Decision {
+        let commits = first_commits.unwrap_or_default();
+        if commits.is_empty() {
+            return Decision {
+                original: false,
+                sample_size: 0,
+                ratio: None,
+            };
+        }
+
+        let same_author_count = commits
+            .iter()
+            .filter(|commit| check_commit_author(self, login, commit))
+            .count();
+        let ratio = same_author_count as f64 / commits.len() as f64;
+
+        Decision {
+            original: ratio > self.threshold,
+            sample_size: commits.len(),
+            ratio: Some(ratio),
+        }
+    }
+
+    /// Return whether a status decided by the heuristic should be checked
+    /// again.
+    pub fn is_stale(
+        &self,
+        heuristic_version: Option<i64>,
+        decided_at: Option<NaiveDateTime>,
+        now: NaiveDateTime,
+    ) -> bool {
+        if heuristic_version.is_none_or(|version| version < HEURISTIC_VERSION) {
+            return true;
+        }
+        match (self.max_age, decided_at) {
+            (Some(max_age), Some(decided_at)) => now - decided_at > max_age,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    fn is_user_email(&self, login: &str, email: &str) -> bool {
+        let email = email.trim().to_lowercase();
+        if self.emails.iter().any(|e| e.to_lowercase() == email) {
+            return true;
+        }
+        match email.strip_suffix(NOREPLY_DOMAIN) {
+            Some(local) => {
+                let name = local.split_once('+').map_or(local, |(_, name)| name);
+                name.eq_ignore_ascii_case(login)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Return whether the commit is authored (or co-authored, if enabled) by the
+/// user according to the policy.
+pub fn check_commit_author(policy: &OriginalityPolicy, login: &str, commit: &CommitEntry) -> bool {
+    if let Some(ref author) = commit.author {
+        if policy.match_login
+            && author
+                .user
+                .as_ref()
+                .is_some_and(|user| user.login.eq(login))
+        {
+            return true;
+        }
+        if policy.match_email
+            && author
+                .email
+                .as_deref()
+                .is_some_and(|email| policy.is_user_email(login, email))
+        {
+            return true;
+        }
+    }
+
+    policy.match_co_authors
+        && co_author_emails(&commit.message).any(|email| policy.is_user_email(login, email))
+}
+
+/// Iterate over the email addresses in `Co-authored-by` trailers of a commit
+/// message.
+fn co_author_emails(message: &str) -> impl Iterator<Item = &str> {
+    message.lines().filter_map(|line| {
+        let line = line.trim();
+        let value = line
+            .get(..CO_AUTHOR_TRAILER.len())
+            .filter(|key| key.eq_ignore_ascii_case(CO_AUTHOR_TRAILER))
+            .map(|_| &line[CO_AUTHOR_TRAILER.len()..])?;
+        let (_, rest) = value.split_once('<')?;
+        let (email, _) = rest.split_once('>')?;
+        Some(email)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(login: Option<&str>, email: Option<&str>, message: &str) -> CommitEntry {
+        serde_json::from_value(serde_json::json!({
+            "author": {
+                "email": email,
+                "user": login.map(|login| serde_json::json!({ "login": login })),
+            },
+            "committedDate": "2024-11-24T10:00:00Z",
+            "message": message,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_commit_author() {
+        let login_only = OriginalityPolicy::default();
+        let by_email = OriginalityPolicy {
+            match_login: false,
+            match_email: true,
+            emails: vec!["Akira@example.com".to_string()],
+            ..Default::default()
+        };
+        let by_co_author = OriginalityPolicy {
+            match_login: false,
+            match_co_authors: true,
+            ..Default::default()
+        };
+
+        let own = commit(Some("akirak"), Some("akira@example.com"), "Initial commit");
+        assert!(check_commit_author(&login_only, "akirak", &own));
+        assert!(check_commit_author(&by_email, "akirak", &own));
+        assert!(!check_commit_author(&by_co_author, "akirak", &own));
+
+        // An unlinked email address
+        let unlinked = commit(None, Some("1234+akirak@users.noreply.github.com"), "Fix");
+        assert!(!check_commit_author(&login_only, "akirak", &unlinked));
+        assert!(check_commit_author(&by_email, "akirak", &unlinked));
+
+        let co_authored = commit(
+            Some("someone"),
+            Some("someone@example.com"),
+            "Add a feature\n\nCo-authored-by: Akira <akirak@users.noreply.github.com>",
+        );
+        assert!(!check_commit_author(&login_only, "akirak", &co_authored));
+        assert!(!check_commit_author(&by_email, "akirak", &co_authored));
+        assert!(check_commit_author(&by_co_author, "akirak", &co_authored));
+
+        let other = commit(Some("someone"), Some("someone@example.com"), "Fix");
+        assert!(!check_commit_author(&by_co_author, "akirak", &other));
+    }
+
+    #[test]
+    fn test_decide() {
+        let policy = OriginalityPolicy::default();
+        let own = || commit(Some("akirak"), None, "");
+        let other = || commit(Some("someone"), None, "");
+
+        // More than half
+        let decision = policy.decide("akirak", Some(vec![own(), own(), other()]));
+        assert!(decision.original);
+        assert_eq!(decision.sample_size, 3);
+        // Exactly half
+        let decision = policy.decide("akirak", Some(vec![own(), other()]));
+        assert!(!decision.original);
+        assert_eq!(decision.ratio, Some(0.5));
+        assert!(!policy.decide("akirak", Some(vec![])).original);
+        assert_eq!(policy.decide("akirak", None).ratio, None);
+
+        let lenient = OriginalityPolicy {
+            threshold: 0.2,
+            ..Default::default()
+        };
+        assert!(
+            lenient
+                .decide("akirak", Some(vec![own(), other(), other()]))
+                .original
+        );
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let policy = OriginalityPolicy {
+            max_age: Some(TimeDelta::days(30)),
+            ..Default::default()
+        };
+        let now =
+            NaiveDateTime::parse_from_str("2024-11-24T10:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        let recent = Some(now - TimeDelta::days(10));
+        let old = Some(now - TimeDelta::days(40));
+
+        assert!(!policy.is_stale(Some(HEURISTIC_VERSION), recent, now));
+        assert!(policy.is_stale(Some(HEURISTIC_VERSION), old, now));
+        assert!(policy.is_stale(Some(HEURISTIC_VERSION - 1), recent, now));
+        assert!(policy.is_stale(None, None, now));
+
+        let no_max_age = OriginalityPolicy {
+            max_age: None,
+            ..Default::default()
+        };
+        assert!(!no_max_age.is_stale(Some(HEURISTIC_VERSION), old, now));
+    }
+}
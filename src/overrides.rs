@@ -0,0 +1,131 @@
+//! Manual originality overrides stored in a TOML file.
+//!
+//! The file maps repositories to their originality, taking precedence over
+//! the commit heuristic:
+//!
+//! ```toml
+//! "akirak/yastar" = true
+//! "some-org/forked-project" = false
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+
+/// Environment variable that specifies the path to the overrides file.
+pub const OVERRIDES_FILE_ENV: &str = "YASTAR_OVERRIDES_FILE";
+
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    entries: BTreeMap<(String, String), bool>,
+}
+
+/// Split `owner/name` into the owner and the name.
+pub fn parse_repository(key: &str) -> anyhow::Result<(String, String)> {
+    match key.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Ok((owner.to_string(), name.to_string()))
+        }
+        _ => Err(anyhow!("Invalid repository {:?}, expected OWNER/NAME", key)),
+    }
+}
+
+/// Return the path to the overrides file, if configured.
+pub fn overrides_path() -> Option<PathBuf> {
+    std::env::var_os(OVERRIDES_FILE_ENV).map(PathBuf::from)
+}
+
+impl Overrides {
+    /// Read overrides from a file. A missing file is treated as empty.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display()))?,
+        };
+        Self::parse(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let table: BTreeMap<String, bool> = toml::from_str(content)?;
+        let entries = table
+            .into_iter()
+            .map(|(key, original)| Ok((parse_repository(&key)?, original)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_toml()?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn to_toml(&self) -> anyhow::Result<String> {
+        let table = self
+            .iter()
+            .map(|(owner, name, original)| (format!("{}/{}", owner, name), original))
+            .collect::<BTreeMap<_, _>>();
+        Ok(toml::to_string(&table)?)
+    }
+
+    pub fn get(&self, owner: &str, name: &str) -> Option<bool> {
+        self.entries
+            .get(&(owner.to_string(), name.to_string()))
+            .copied()
+    }
+
+    pub fn set(&mut self, owner: &str, name: &str, original: bool) {
+        self.entries
+            .insert((owner.to_string(), name.to_string()), original);
+    }
+
+    /// Remove an override, and return whether it existed.
+    pub fn unset(&mut self, owner: &str, name: &str) -> bool {
+        self.entries
+            .remove(&(owner.to_string(), name.to_string()))
+            .is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, bool)> {
+        self.entries
+            .iter()
+            .map(|((owner, name), original)| (owner.as_str(), name.as_str(), *original))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_overrides() {
+        let mut overrides = Overrides::parse(
+            r#"
+            "akirak/yastar" = true
+            "some-org/forked-project" = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(overrides.get("akirak", "yastar"), Some(true));
+        assert_eq!(overrides.get("some-org", "forked-project"), Some(false));
+        assert_eq!(overrides.get("akirak", "other"), None);
+
+        overrides.set("akirak", "other", false);
+        assert!(overrides.unset("akirak", "yastar"));
+        assert!(!overrides.unset("akirak", "yastar"));
+
+        let reparsed = Overrides::parse(&overrides.to_toml().unwrap()).unwrap();
+        assert_eq!(
+            reparsed.iter().collect::<Vec<_>>(),
+            vec![
+                ("akirak", "other", false),
+                ("some-org", "forked-project", false)
+            ]
+        );
+
+        assert!(Overrides::parse(r#""yastar" = true"#).is_err());
+        assert!(Overrides::parse(r#""akirak/yastar" = "yes""#).is_err());
+    }
+}
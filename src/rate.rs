@@ -0,0 +1,147 @@
+//! Number of new stars per period, which shows spikes and slowdowns hidden by
+//! the cumulative histories.
+
+use std::num::NonZeroUsize;
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+
+/// Length of the periods to count new stars in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RatePeriod {
+    Day,
+    /// Week starting on Monday
+    Week,
+    Month,
+}
+
+impl RatePeriod {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    /// Return the first date of the period containing the date.
+    pub fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => date,
+            Self::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Self::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    /// Return the first date of the next period, given the first date of a
+    /// period.
+    pub fn next(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => start + Duration::days(1),
+            Self::Week => start + Duration::days(7),
+            Self::Month => start + Months::new(1),
+        }
+    }
+}
+
+/// New stars per period, ordered by date.
+#[derive(Debug)]
+pub struct StarRate {
+    pub period: RatePeriod,
+    /// Number of new stars of each period, identified by its first date.
+    /// Periods without stars are included with zero.
+    pub counts: Vec<(NaiveDate, i64)>,
+    /// Trailing average over the window, starting from the first period with
+    /// a full window.
+    pub average: Option<(usize, Vec<(NaiveDate, f64)>)>,
+}
+
+impl StarRate {
+    /// Count the daily new stars per period. If `window` is given, the moving
+    /// average over that number of periods is also computed.
+    pub fn new(
+        daily: &[(NaiveDate, i64)],
+        period: RatePeriod,
+        window: Option<NonZeroUsize>,
+    ) -> Self {
+        let mut counts: Vec<(NaiveDate, i64)> = Vec::new();
+
+        for (date, count) in daily {
+            let start = period.start(*date);
+            // Fill the periods without stars since the last one
+            while let Some(&(last, _)) = counts.last() {
+                if last >= start {
+                    break;
+                }
+                counts.push((period.next(last), 0));
+            }
+            match counts.last_mut() {
+                Some((_, total)) => *total += count,
+                None => counts.push((start, *count)),
+            }
+        }
+
+        let average = window.map(|window| (window.get(), moving_average(&counts, window)));
+
+        Self {
+            period,
+            counts,
+            average,
+        }
+    }
+}
+
+fn moving_average(counts: &[(NaiveDate, i64)], window: NonZeroUsize) -> Vec<(NaiveDate, f64)> {
+    let window = window.get();
+    counts
+        .windows(window)
+        .map(|slice| {
+            let sum = slice.iter().map(|(_, count)| count).sum::<i64>();
+            (slice.last().unwrap().0, sum as f64 / window as f64)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+
+    #[test]
+    fn test_star_rate() {
+        let daily = [
+            (date("2024-09-21"), 1),
+            (date("2024-09-22"), 2),
+            (date("2024-09-23"), 3),
+            (date("2024-10-08"), 4),
+        ];
+
+        let rate = StarRate::new(&daily, RatePeriod::Week, NonZeroUsize::new(2));
+        assert_eq!(
+            rate.counts,
+            vec![
+                (date("2024-09-16"), 3),
+                (date("2024-09-23"), 3),
+                (date("2024-09-30"), 0),
+                (date("2024-10-07"), 4),
+            ]
+        );
+        assert_eq!(
+            rate.average,
+            Some((
+                2,
+                vec![
+                    (date("2024-09-23"), 3.0),
+                    (date("2024-09-30"), 1.5),
+                    (date("2024-10-07"), 2.0),
+                ]
+            ))
+        );
+
+        let rate = StarRate::new(&daily, RatePeriod::Month, None);
+        assert_eq!(
+            rate.counts,
+            vec![(date("2024-09-01"), 6), (date("2024-10-01"), 4)]
+        );
+        assert_eq!(rate.average, None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Summary report of the database.
+
+use std::fmt::Write;
+
+use chrono::{NaiveDateTime, TimeDelta};
+use duckdb::Connection;
+use serde::Serialize;
+
+use crate::db;
+
+/// Periods for which the stars gained are counted, in days.
+const RECENT_PERIODS: &[i64] = &[7, 30, 365];
+
+/// Label of repositories without a primary language in the table.
+const NO_LANGUAGE: &str = "(none)";
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    /// Stars of the original repositories.
+    pub total_stars: i64,
+    pub languages: Vec<LanguageStars>,
+    pub top_repositories: Vec<RepositoryStars>,
+    pub recent_stars: Vec<RecentStars>,
+    pub originality: OriginalityCounts,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LanguageStars {
+    /// `None` for repositories without a primary language.
+    pub language: Option<String>,
+    pub stars: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepositoryStars {
+    pub owner: String,
+    pub name: String,
+    pub stars: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecentStars {
+    pub days: i64,
+    /// Stars gained in the period that have not been removed.
+    pub stars: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OriginalityCounts {
+    pub original: usize,
+    pub not_original: usize,
+}
+
+impl Stats {
+    /// Collect the stats, with at most `top` repositories.
+    pub fn collect(conn: &Connection, top: usize, now: NaiveDateTime) -> anyhow::Result<Self> {
+        let star_counts = db::get_original_star_counts(conn)?;
+
+        let languages = db::get_total_stars_by_language(conn)?
+            .into_iter()
+            .map(|(language, stars)| LanguageStars { language, stars })
+            .collect();
+
+        let recent_stars = RECENT_PERIODS
+            .iter()
+            .map(|&days| {
+                Ok(RecentStars {
+                    days,
+                    stars: db::count_stars_since(conn, now - TimeDelta::days(days))?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let records = db::original_status_records(conn)?;
+        let original = records.iter().filter(|record| record.original).count();
+
+        Ok(Self {
+            total_stars: star_counts.iter().map(|(_, _, stars)| stars).sum(),
+            languages,
+            top_repositories: star_counts
+                .into_iter()
+                .take(top)
+                .map(|(owner, name, stars)| RepositoryStars { owner, name, stars })
+                .collect(),
+            recent_stars,
+            originality: OriginalityCounts {
+                original,
+                not_original: records.len() - original,
+            },
+        })
+    }
+
+    /// Render the stats as a human-readable table.
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+
+        write_section(
+            &mut out,
+            "Stars",
+            std::iter::once(("Total".to_string(), self.total_stars)).chain(
+                self.recent_stars
+                    .iter()
+                    .map(|recent| (format!("Last {} days", recent.days), recent.stars)),
+            ),
+        );
+        write_section(
+            &mut out,
+            "Languages",
+            self.languages.iter().map(|entry| {
+                (
+                    entry.language.as_deref().unwrap_or(NO_LANGUAGE).to_string(),
+                    entry.stars,
+                )
+            }),
+        );
+        write_section(
+            &mut out,
+            "Top repositories",
+            self.top_repositories
+                .iter()
+                .map(|repo| (format!("{}/{}", repo.owner, repo.name), repo.stars)),
+        );
+        write_section(
+            &mut out,
+            "Originality",
+            [
+                ("Original".to_string(), self.originality.original as i64),
+                (
+                    "Not original".to_string(),
+                    self.originality.not_original as i64,
+                ),
+            ],
+        );
+
+        out
+    }
+}
+
+/// Write a section of the table with the labels and values aligned.
+fn write_section(out: &mut String, title: &str, rows: impl IntoIterator<Item = (String, i64)>) {
+    let rows = rows.into_iter().collect::<Vec<_>>();
+    let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    let value_width = rows
+        .iter()
+        .map(|(_, value)| value.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    let _ = writeln!(out, "{}", title);
+    for (label, value) in rows {
+        let _ = writeln!(out, "  {:<label_width$}  {:>value_width$}", label, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_table() {
+        let stats = Stats {
+            total_stars: 1234,
+            languages: vec![
+                LanguageStars {
+                    language: Some("Rust".to_string()),
+                    stars: 1200,
+                },
+                LanguageStars {
+                    language: None,
+                    stars: 34,
+                },
+            ],
+            top_repositories: vec![RepositoryStars {
+                owner: "akirak".to_string(),
+                name: "yastar".to_string(),
+                stars: 1234,
+            }],
+            recent_stars: vec![RecentStars { days: 7, stars: 5 }],
+            originality: OriginalityCounts {
+                original: 3,
+                not_original: 10,
+            },
+        };
+
+        assert_eq!(
+            stats.to_table(),
+            "Stars
+  Total        1234
+  Last 7 days     5
+
+Languages
+  Rust    1200
+  (none)    34
+
+Top repositories
+  akirak/yastar  1234
+
+Originality
+  Original       3
+  Not original  10
+"
+        );
+    }
+}